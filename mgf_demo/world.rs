@@ -67,8 +67,8 @@ pub struct World<R: Resources> {
     cam_dir: Vector3<f32>,
     cam_up: Vector3<f32>,
     bodies: RigidBodyVec,
-    bvh_ids: Vec<usize>,
-    bvh: BVH<AABB, usize>,
+    proxy_ids: Vec<usize>,
+    broadphase: DynamicTree<usize>,
     terrain: Mesh,
     locals: Buffer<R, Locals>,
     sphere_model: (Buffer<R, Vertex>, Slice<R>),
@@ -155,8 +155,8 @@ impl<R: Resources> World<R> {
             cam_dir: Vector3::unit_x(),
             cam_up: Vector3::unit_y(),
             bodies: RigidBodyVec::new(),
-            bvh_ids: Vec::new(),
-            bvh: BVH::new(),
+            proxy_ids: Vec::new(),
+            broadphase: DynamicTree::new(),
             terrain:  terrain_mesh,
             locals: factory.create_constant_buffer(1),
             sphere_model:  factory.create_vertex_buffer_with_slice(
@@ -178,8 +178,8 @@ impl<R: Resources> World<R> {
     pub fn add_body(&mut self, collider: Component, mass: f32, restitution: f32, friction: f32, world_force: Vector3<f32>) -> usize {
         let id: usize = self.bodies.add_body(collider, mass, restitution, friction, world_force).into();
         let bounds: AABB = self.bodies.collider[id].bounds();
-        let bvh_id = self.bvh.insert(&(bounds + 0.25), id);
-        self.bvh_ids.push(bvh_id);
+        let proxy_id = self.broadphase.create_proxy(bounds, id);
+        self.proxy_ids.push(proxy_id);
         id
     }
 
@@ -232,10 +232,7 @@ impl<R: Resources> World<R> {
 
         for (i, collider) in self.bodies.colliders().enumerate() {
             let bounds: AABB = collider.bounds();
-            if !self.bvh[self.bvh_ids[i]].contains(&bounds) {
-                self.bvh.remove(self.bvh_ids[i]);
-                self.bvh_ids[i] = self.bvh.insert(&(bounds + 0.25), i);
-            }
+            self.broadphase.move_proxy(self.proxy_ids[i], bounds, collider.1);
 
             collider.local_contacts(
                 &self.terrain,
@@ -251,44 +248,38 @@ impl<R: Resources> World<R> {
                     )
                 }
             );
-            
-            // If we're the first body integrated we don't need to do anything.
-            if i == 0 {
-                continue;
-            }
+        }
 
-            let bvh = &self.bvh;
-            bvh.query(
-                &bounds,
-                |&collider_i| {
-                    // For rigid body collisions, collect the contacts into a pruner
-                    // and then put that into a manifold.
-                    if collider_i >= i {
-                        return;
+        self.broadphase.update_pairs(|_, _| {});
+        let bodies = &self.bodies;
+        let broadphase = &self.broadphase;
+        broadphase.pairs(
+            |a, b| {
+                let (a, b) = (broadphase[a], broadphase[b]);
+                // For rigid body collisions, collect the contacts into a pruner
+                // and then put that into a manifold.
+                let mut pruner: ContactPruner = ContactPruner::new();
+                bodies.collider[a].local_contacts(
+                    &bodies.collider[b],
+                    |lc| {
+                        pruner.push(lc);
                     }
-                    let mut pruner: ContactPruner = ContactPruner::new();
-                    collider.local_contacts(
-                        &self.bodies.collider[collider_i],
-                        |lc| {
-                            pruner.push(lc);
-                        }
-                    );
-                    let manifold = Manifold::from(pruner);
-                    if manifold.len() == 0 {
-                        return;
-                    }
-                    solver.add_constraint(
-                        ContactConstraint::new(
-                            &self.bodies,
-                            RigidBodyRef::Dynamic(i),
-                            RigidBodyRef::Dynamic(collider_i),
-                            manifold,
-                            dt,
-                        )
-                    );
+                );
+                let manifold = Manifold::from(pruner);
+                if manifold.len() == 0 {
+                    return;
                 }
-            );
-        }
+                solver.add_constraint(
+                    ContactConstraint::new(
+                        bodies,
+                        RigidBodyRef::Dynamic(a),
+                        RigidBodyRef::Dynamic(b),
+                        manifold,
+                        dt,
+                    )
+                );
+            }
+        );
 
         solver.solve(&mut self.bodies, 20);
     }
@@ -67,6 +67,24 @@ impl<B: Bound, T: Copy + Clone + Shape + BoundedBy<B>> BoundedBy<B> for Moving<T
     }
 }
 
+impl<B, T> BoundedBy<B> for Rotating<T>
+where
+    B: Bound,
+    Sphere: BoundedBy<B>,
+    T: Copy + Clone + Shape + BoundedBy<Sphere>,
+{
+    /// The bounds for a rotating object is the sphere centered on the object
+    /// that encloses it at every orientation, extended in the direction and
+    /// magnitude of the velocity.
+    fn bounds(&self) -> B {
+        let c = self.0.center();
+        let s = self.0.bounds();
+        let s = Sphere{ c, r: (s.c - c).magnitude() + s.r }.bounds();
+        let e = s + self.1;
+        B::combine(&s, &e)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // AABBs satisfy Bound
 
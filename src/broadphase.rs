@@ -0,0 +1,272 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::vec::Vec;
+
+use cgmath::Vector3;
+
+use crate::bounds::Bound;
+use crate::bvh::BVH;
+use crate::collision::{Contains, Overlaps};
+use crate::geom::AABB;
+use crate::pool::Pool;
+
+/// A type that supplies constants to a DynamicTree.
+pub trait BroadphaseParams {
+    /// Distance the bounds of a proxy are extended by in every direction.
+    /// Proxies are only reinserted once they leave their extended bounds.
+    const MARGIN: f32;
+
+    /// Multiple of a proxy's displacement its bounds are extended by in the
+    /// direction of motion, predicting where the proxy will be next.
+    const DISPLACEMENT_MULTIPLIER: f32;
+}
+
+/// Default parameters supplied for broadphases.
+pub struct DefaultBroadphaseParams {}
+
+impl BroadphaseParams for DefaultBroadphaseParams {
+    const MARGIN: f32 = 0.1;
+    const DISPLACEMENT_MULTIPLIER: f32 = 2.0;
+}
+
+/// A structure that finds pairs of objects whose bounds overlap.
+///
+/// Objects are represented by proxies which are given an ID when created. A
+/// broadphase keeps a persistent set of overlapping pairs of proxies, which
+/// is brought up to date by update_pairs.
+pub trait Broadphase<V>: Index<usize, Output = V> {
+    /// Adds a proxy with the given bounds and returns its ID. The ID remains
+    /// valid until the proxy is destroyed.
+    fn create_proxy(&mut self, bounds: AABB, val: V) -> usize;
+
+    /// Removes a proxy, along with every pair it belongs to.
+    fn destroy_proxy(&mut self, id: usize) -> V;
+
+    /// Updates the bounds of a proxy that has moved by displacement since its
+    /// last update. Returns true if the stored bounds of the proxy changed.
+    fn move_proxy(&mut self, id: usize, bounds: AABB, displacement: Vector3<f32>) -> bool;
+
+    /// Brings the set of overlapping pairs up to date. callback is called once
+    /// for each new pair with the IDs of the two proxies, lowest first.
+    fn update_pairs<F: FnMut(usize, usize)>(&mut self, callback: F);
+
+    /// Calls callback for each pair of proxies that overlapped as of the last
+    /// call to update_pairs, lowest ID first.
+    fn pairs<F: FnMut(usize, usize)>(&self, callback: F);
+}
+
+/// An object stored in a DynamicTree.
+struct Proxy<V> {
+    leaf: usize,
+    val: V,
+}
+
+/// A broadphase built on a BVH of enlarged ("fat") AABBs.
+///
+/// Each proxy is stored with bounds that are extended by a margin and by its
+/// predicted motion, so small movements do not require the tree to change.
+/// Proxies that do leave their fat bounds are reinserted and buffered so that
+/// update_pairs only needs to search for pairs involving them. The set of
+/// overlapping pairs is kept between updates.
+pub struct DynamicTree<V, Params = DefaultBroadphaseParams>
+where
+    Params: BroadphaseParams
+{
+    bvh: BVH<AABB, usize>,
+    proxies: Pool<Proxy<V>>,
+    moved: Vec<usize>,
+    /// Whether each proxy ID is in moved.
+    is_moved: Vec<bool>,
+    pairs: BTreeSet<(usize, usize)>,
+    params: PhantomData<Params>,
+}
+
+impl<V, Params: BroadphaseParams> DynamicTree<V, Params> {
+    pub fn new() -> Self {
+        DynamicTree {
+            bvh: BVH::new(),
+            proxies: Pool::new(),
+            moved: Vec::new(),
+            is_moved: Vec::new(),
+            pairs: BTreeSet::new(),
+            params: PhantomData,
+        }
+    }
+
+    /// Returns the fat bounds currently stored for a proxy.
+    pub fn fat_bounds(&self, id: usize) -> AABB {
+        self.bvh[self.proxies[id].leaf]
+    }
+
+    /// Buffers a proxy for the next update_pairs, unless it already is.
+    fn mark_moved(&mut self, id: usize) {
+        if !self.is_moved[id] {
+            self.is_moved[id] = true;
+            self.moved.push(id);
+        }
+    }
+}
+
+impl<V, Params: BroadphaseParams> Broadphase<V> for DynamicTree<V, Params> {
+    /// Adds a proxy with the given bounds to the tree and returns its ID. The
+    /// ID remains valid until the proxy is destroyed.
+    fn create_proxy(&mut self, bounds: AABB, val: V) -> usize {
+        let id = self.proxies.push(Proxy{ leaf: 0, val });
+        self.proxies[id].leaf = self.bvh.insert(&(bounds + Params::MARGIN), id);
+        if self.is_moved.len() <= id {
+            self.is_moved.resize(id + 1, false);
+        }
+        self.mark_moved(id);
+        id
+    }
+
+    /// Removes a proxy from the tree, along with every pair it belongs to.
+    fn destroy_proxy(&mut self, id: usize) -> V {
+        let proxy = self.proxies.remove(id);
+        self.bvh.remove(proxy.leaf);
+        if self.is_moved[id] {
+            self.is_moved[id] = false;
+            self.moved.retain(|&moved| moved != id);
+        }
+        self.pairs.retain(|&(a, b)| a != id && b != id);
+        proxy.val
+    }
+
+    /// Updates the bounds of a proxy that has moved by displacement since its
+    /// last update. If the new bounds are still enclosed by the proxy's fat
+    /// bounds nothing is changed and false is returned. Otherwise the proxy
+    /// is reinserted with new fat bounds and true is returned.
+    fn move_proxy(&mut self, id: usize, bounds: AABB, displacement: Vector3<f32>) -> bool {
+        let leaf = self.proxies[id].leaf;
+        if self.bvh[leaf].contains(&bounds) {
+            return false;
+        }
+        let fat = bounds + Params::MARGIN;
+        let fat = AABB::combine(&fat, &(fat + displacement * Params::DISPLACEMENT_MULTIPLIER));
+        self.bvh.remove(leaf);
+        self.proxies[id].leaf = self.bvh.insert(&fat, id);
+        self.mark_moved(id);
+        true
+    }
+
+    /// Finds new overlapping pairs involving proxies that were created or
+    /// moved since the last update, and drops pairs whose fat bounds no
+    /// longer overlap. callback is called once for each new pair with the IDs
+    /// of the two proxies, lowest first.
+    fn update_pairs<F>(&mut self, mut callback: F)
+    where
+        F: FnMut(usize, usize)
+    {
+        if self.moved.is_empty() {
+            return;
+        }
+        let moved = &self.is_moved;
+        let bvh = &self.bvh;
+        let proxies = &self.proxies;
+        self.pairs.retain(|&(a, b)| {
+            if !moved[a] && !moved[b] {
+                return true;
+            }
+            bvh[proxies[a].leaf].overlaps(&bvh[proxies[b].leaf])
+        });
+        for &id in self.moved.iter() {
+            let fat = self.bvh[self.proxies[id].leaf];
+            let pairs = &mut self.pairs;
+            self.bvh.query(&fat, |&other| {
+                if other == id {
+                    return;
+                }
+                let pair = if id < other { (id, other) } else { (other, id) };
+                if pairs.insert(pair) {
+                    callback(pair.0, pair.1);
+                }
+            });
+            self.is_moved[id] = false;
+        }
+        self.moved.clear();
+    }
+
+    /// Calls callback for each pair of proxies whose fat bounds overlapped as
+    /// of the last call to update_pairs.
+    fn pairs<F>(&self, mut callback: F)
+    where
+        F: FnMut(usize, usize)
+    {
+        for &(a, b) in self.pairs.iter() {
+            callback(a, b);
+        }
+    }
+}
+
+impl<V, Params: BroadphaseParams> Default for DynamicTree<V, Params> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V, Params: BroadphaseParams> Index<usize> for DynamicTree<V, Params> {
+    type Output = V;
+
+    fn index(&self, id: usize) -> &V {
+        &self.proxies[id].val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod broadphase {
+        use cgmath::{Point3, Vector3};
+        use crate::broadphase::*;
+        use crate::collision::Contains;
+        use crate::geom::AABB;
+
+        fn unit_box(x: f32) -> AABB {
+            AABB{ c: Point3::new(x, 0.0, 0.0), r: Vector3::new(0.5, 0.5, 0.5) }
+        }
+
+        #[test]
+        fn test_dynamic_tree() {
+            let mut tree: DynamicTree<&str> = DynamicTree::new();
+            let a = tree.create_proxy(unit_box(0.0), "a");
+            let b = tree.create_proxy(unit_box(0.9), "b");
+            let c = tree.create_proxy(unit_box(5.0), "c");
+            let mut new_pairs = Vec::new();
+            tree.update_pairs(|a, b| new_pairs.push((a, b)));
+            assert_eq!(new_pairs, vec![(a, b)]);
+            assert_eq!(tree[c], "c");
+
+            // Small movements stay within the fat bounds.
+            assert!(!tree.move_proxy(b, unit_box(0.95), Vector3::new(0.05, 0.0, 0.0)));
+            assert!(tree.move_proxy(c, unit_box(1.5), Vector3::new(-3.5, 0.0, 0.0)));
+            assert!(tree.fat_bounds(c).contains(&unit_box(-5.0)));
+            new_pairs.clear();
+            tree.update_pairs(|a, b| new_pairs.push((a, b)));
+            new_pairs.sort();
+            // The fat bounds of c extend ahead along its motion and reach a.
+            assert_eq!(new_pairs, vec![(a, c), (b, c)]);
+
+            assert_eq!(tree.destroy_proxy(b), "b");
+            assert!(tree.move_proxy(c, unit_box(8.0), Vector3::new(6.5, 0.0, 0.0)));
+            tree.update_pairs(|_, _| panic!("no new pairs expected"));
+            let mut pairs = Vec::new();
+            tree.pairs(|a, b| pairs.push((a, b)));
+            assert!(pairs.is_empty());
+        }
+    }
+}
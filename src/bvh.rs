@@ -14,14 +14,18 @@
 // along with MGF. If not, see <http://www.gnu.org/licenses/>.
 
 use std::cmp;
+use std::collections::BinaryHeap;
+use std::f32;
 use std::ops::Index;
 
+use cgmath::{InnerSpace, Point3};
 use smallvec::SmallVec;
 
 use crate::bounds::{Bound, BoundedBy};
 use crate::pool::Pool;
 
 use crate::collision::{Intersects, Intersection};
+use crate::frustum::{Classify, Containment, Frustum};
 
 use serde::{Serialize, Deserialize};
 
@@ -30,6 +34,9 @@ use serde::{Serialize, Deserialize};
 pub struct BVH<B: Bound, V> {
     root: usize,
     pool: Pool<BVHNode<B, V>>,
+    /// Node at which the next call to optimize resumes.
+    #[serde(skip)]
+    cursor: usize,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -46,6 +53,127 @@ enum BVHNodeType<V> {
     Parent(usize, usize),
 }
 
+/// Partitions leaves along the axis their centers are most spread out on
+/// and returns the number of leaves on the left. The split is chosen with
+/// the surface area heuristic evaluated over SAH_BINS buckets, falling
+/// back to the median when the centers coincide or no split is cheaper
+/// than the node itself.
+fn split_leaves<B: Bound>(leaves: &mut [(usize, B, Point3<f32>)]) -> usize {
+    const SAH_BINS: usize = 16;
+    let (lower, upper) = leaves.iter().fold(
+        (Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+         Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+        |(lower, upper), &(_, _, c)| {
+            (Point3::new(lower.x.min(c.x), lower.y.min(c.y), lower.z.min(c.z)),
+             Point3::new(upper.x.max(c.x), upper.y.max(c.y), upper.z.max(c.z)))
+        }
+    );
+    let extent = upper - lower;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let median = leaves.len() / 2;
+    if extent[axis] <= 0.0 {
+        return median;
+    }
+    let scale = SAH_BINS as f32 / extent[axis];
+    let bin = |c: Point3<f32>| -> usize {
+        let offset = (c[axis] - lower[axis]) * scale;
+        cmp::min(offset as usize, SAH_BINS - 1)
+    };
+
+    let mut counts = [0usize; SAH_BINS];
+    let mut bins: [Option<B>; SAH_BINS] = [None; SAH_BINS];
+    for &(_, bounds, c) in leaves.iter() {
+        let i = bin(c);
+        counts[i] += 1;
+        bins[i] = Some(bins[i].map_or(bounds, |b| B::combine(&b, &bounds)));
+    }
+
+    // Sweep from the right to find the cost of every right hand side, then
+    // sweep from the left to find the cheapest split.
+    let mut right_costs = [f32::INFINITY; SAH_BINS];
+    let (mut bounds, mut count): (Option<B>, usize) = (None, 0);
+    for i in (1..SAH_BINS).rev() {
+        bounds = combine_bins(bounds, bins[i]);
+        count += counts[i];
+        if let Some(bounds) = bounds {
+            right_costs[i] = bounds.surface_area() * count as f32;
+        }
+    }
+    let mut split = None;
+    let mut best_cost = f32::INFINITY;
+    let (mut bounds, mut count): (Option<B>, usize) = (None, 0);
+    for i in 1..SAH_BINS {
+        bounds = combine_bins(bounds, bins[i - 1]);
+        count += counts[i - 1];
+        if let Some(bounds) = bounds {
+            let cost = bounds.surface_area() * count as f32 + right_costs[i];
+            if count > 0 && count < leaves.len() && cost < best_cost {
+                split = Some(i);
+                best_cost = cost;
+            }
+        }
+    }
+    let total = bins.iter().fold(None, |total, &b| combine_bins(total, b));
+    let leaf_cost = total.map_or(f32::INFINITY, |b| b.surface_area() * leaves.len() as f32);
+    let split = match split {
+        Some(split) if best_cost < leaf_cost => split,
+        _ => {
+            leaves.sort_unstable_by(|a, b| {
+                a.2[axis].partial_cmp(&b.2[axis]).unwrap_or(cmp::Ordering::Equal)
+            });
+            return median;
+        },
+    };
+
+    let mut mid = 0;
+    for i in 0..leaves.len() {
+        if bin(leaves[i].2) < split {
+            leaves.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+/// Combines two optional bounds.
+fn combine_bins<B: Bound>(a: Option<B>, b: Option<B>) -> Option<B> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(B::combine(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// A value ordered by a distance, used to sort nodes and entries in best first
+/// searches.
+struct ByDist<T>(f32, T);
+
+impl<T> PartialEq for ByDist<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for ByDist<T> {}
+
+impl<T> PartialOrd for ByDist<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ByDist<T> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(cmp::Ordering::Equal)
+    }
+}
+
 impl<B, V> Clone for BVH<B, V>
 where
     B: Bound,
@@ -55,6 +183,7 @@ where
         BVH {
             root: self.root,
             pool: self.pool.clone(),
+            cursor: self.cursor,
         }
     }
 }
@@ -89,6 +218,7 @@ impl<B: Bound, V> BVH<B, V> {
         BVH {
             root: 0,
             pool: Pool::new(),
+            cursor: 0,
         }
     }
 
@@ -97,7 +227,73 @@ impl<B: Bound, V> BVH<B, V> {
         BVH {
             root: 0,
             pool: Pool::with_capacity(cap),
+            cursor: 0,
+        }
+    }
+
+    /// Builds a BVH from a collection of items top-down, splitting each node
+    /// where the binned surface area heuristic is lowest. This produces a
+    /// better tree than inserting the items one at a time, which makes it the
+    /// preferred way to build large static sets such as meshes.
+    ///
+    /// Leaves are assigned IDs in the order the items are given, so the ID of
+    /// the nth item is n.
+    pub fn build<K, I>(items: I) -> Self
+    where
+        K: BoundedBy<B>,
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut bvh = BVH::new();
+        for (key, val) in items {
+            bvh.insert_node(key.bounds(), BVHNodeType::Leaf(val));
+        }
+        if bvh.empty() {
+            return bvh;
+        }
+        let mut leaves: Vec<(usize, B, Point3<f32>)> = (0..bvh.pool.len())
+            .map(|i| (i, bvh.pool[i].bounds, bvh.pool[i].bounds.center()))
+            .collect();
+        // Split ranges of leaves with an explicit stack so that badly balanced
+        // trees cannot overflow the call stack. Each range is given the parent
+        // it hangs from and whether it is the left child.
+        let mut parents = Vec::new();
+        let mut stack = vec![(0, leaves.len(), None)];
+        while let Some((start, end, parent)) = stack.pop() {
+            let node = if end - start == 1 {
+                bvh.pool[leaves[start].0].height = 0;
+                leaves[start].0
+            } else {
+                let mid = start + split_leaves(&mut leaves[start..end]);
+                let node = bvh.insert_node(leaves[start].1, BVHNodeType::Parent(0, 0));
+                parents.push(node);
+                stack.push((start, mid, Some((node, true))));
+                stack.push((mid, end, Some((node, false))));
+                node
+            };
+            match parent {
+                Some((parent, is_left)) => {
+                    if let BVHNodeType::Parent(ref mut lchild, ref mut rchild) = bvh.pool[parent].node_type {
+                        if is_left {
+                            *lchild = node;
+                        } else {
+                            *rchild = node;
+                        }
+                    }
+                    bvh.pool[node].parent = parent;
+                },
+                None => bvh.root = node,
+            }
+        }
+        // Children are always created after their parents, so walking the
+        // parents backwards fixes up the bounds from the bottom up.
+        for &node in parents.iter().rev() {
+            if let BVHNodeType::Parent(lchild, rchild) = bvh.pool[node].node_type {
+                bvh.pool[node].bounds = B::combine(&bvh.pool[lchild].bounds, &bvh.pool[rchild].bounds);
+                bvh.pool[node].height =
+                    1 + cmp::max(bvh.pool[lchild].height, bvh.pool[rchild].height);
+            }
         }
+        bvh
     }
 
     /// Determines if the BVH is empty.
@@ -259,6 +455,54 @@ impl<B: Bound, V> BVH<B, V> {
         }
     }
 
+    /// Replaces the bounds of a leaf node and enlarges or shrinks the bounds of
+    /// its ancestors to match. The structure of the tree is left unchanged, so
+    /// no nodes are reallocated and all IDs remain valid, but the quality of
+    /// the tree may degrade if leaves move far from where they were inserted.
+    pub fn update_leaf<K: BoundedBy<B>>(&mut self, leaf: usize, key: &K) {
+        self.pool[leaf].bounds = key.bounds();
+        let mut i = leaf;
+        while i != self.root {
+            i = self.pool[i].parent;
+            if let BVHNodeType::Parent(child1, child2) = self.pool[i].node_type {
+                self.pool[i].bounds =
+                    B::combine(&self.pool[child1].bounds, &self.pool[child2].bounds);
+            }
+        }
+    }
+
+    /// Recomputes the bounds of every leaf with the given function and then
+    /// refits every parent to its children, without changing the structure of
+    /// the tree.
+    pub fn refit<F>(&mut self, mut bounds: F)
+    where
+        F: FnMut(&V) -> B
+    {
+        if self.empty() {
+            return;
+        }
+        // Parents are always visited before their children, so walking the
+        // order backwards refits children first.
+        let mut order = Vec::with_capacity(self.pool.len());
+        let mut stack = SmallVec::<[usize; 64]>::new();
+        stack.push(self.root);
+        while let Some(top) = stack.pop() {
+            order.push(top);
+            if let BVHNodeType::Parent(lchild, rchild) = self.pool[top].node_type {
+                stack.push(lchild);
+                stack.push(rchild);
+            }
+        }
+        for &i in order.iter().rev() {
+            let new_bounds = match self.pool[i].node_type {
+                BVHNodeType::Leaf(ref val) => bounds(val),
+                BVHNodeType::Parent(lchild, rchild) =>
+                    B::combine(&self.pool[lchild].bounds, &self.pool[rchild].bounds),
+            };
+            self.pool[i].bounds = new_bounds;
+        }
+    }
+
     /// Returns the index of the root node.
     pub fn root(&self) -> usize {
         if self.empty() {
@@ -341,6 +585,40 @@ impl<B: Bound, V> BVH<B, V> {
         }
     }
 
+    /// Finds each entry in the BVH whose bound is at least partially inside of
+    /// a frustum. Subtrees whose bounds lie entirely outside of the frustum
+    /// are skipped, and subtrees whose bounds lie entirely inside are reported
+    /// without testing any of their children.
+    pub fn query_frustum<F>(&self, frustum: &Frustum, mut callback: F)
+    where
+        Frustum: Classify<B>,
+        F: FnMut(&V)
+    {
+        if self.empty() {
+            return;
+        }
+        // Each entry records whether the node is known to be inside.
+        let mut stack = SmallVec::<[(usize, bool); 64]>::new();
+        stack.push((self.root, false));
+        while let Some((top, inside)) = stack.pop() {
+            let inside = inside || match frustum.classify(&self.pool[top].bounds) {
+                Containment::Outside => continue,
+                Containment::Intersects => false,
+                Containment::Inside => true,
+            };
+            match self.pool[top].node_type {
+                BVHNodeType::Leaf(ref val) => {
+                    callback(val);
+                },
+
+                BVHNodeType::Parent(lchild, rchild) => {
+                    stack.push((lchild, inside));
+                    stack.push((rchild, inside));
+                }
+            }
+        }
+    }
+
     /// Finds all entries that intersect a ray or segment.
     pub fn raytrace<Arg, F>(&self, arg: &Arg, mut callback: F)
     where
@@ -368,6 +646,372 @@ impl<B: Bound, V> BVH<B, V> {
         }
     }
 
+    /// Finds the first entry hit by a ray or segment. Children are visited in
+    /// the order the ray enters their bounds, and any node entered after the
+    /// closest confirmed hit is skipped.
+    ///
+    /// narrowphase is called for each leaf whose bounds are hit and must
+    /// return the exact intersection with the entry, if any. Returns the
+    /// closest entry along with its intersection.
+    pub fn raycast_closest<Arg, F>(&self, arg: &Arg, mut narrowphase: F) -> Option<(&V, Intersection)>
+    where
+        Arg: Intersects<B>,
+        F: FnMut(&V) -> Option<Intersection>
+    {
+        if self.empty() {
+            return None;
+        }
+        let root_t = arg.intersection(&self.pool[self.root].bounds)?.t;
+        let mut best: Option<(&V, Intersection)> = None;
+        let mut best_t = f32::INFINITY;
+        let mut stack = SmallVec::<[(usize, f32); 64]>::new();
+        stack.push((self.root, root_t));
+        while let Some((top, top_t)) = stack.pop() {
+            if top_t > best_t {
+                continue;
+            }
+            match self.pool[top].node_type {
+                BVHNodeType::Leaf(ref val) => {
+                    if let Some(inter) = narrowphase(val) {
+                        if inter.t < best_t {
+                            best_t = inter.t;
+                            best = Some((val, inter));
+                        }
+                    }
+                },
+
+                BVHNodeType::Parent(lchild, rchild) => {
+                    let lt = arg.intersection(&self.pool[lchild].bounds).map(|i| i.t);
+                    let rt = arg.intersection(&self.pool[rchild].bounds).map(|i| i.t);
+                    match (lt, rt) {
+                        (Some(lt), Some(rt)) => {
+                            // Push the nearer child last so it is visited first.
+                            if lt <= rt {
+                                stack.push((rchild, rt));
+                                stack.push((lchild, lt));
+                            } else {
+                                stack.push((lchild, lt));
+                                stack.push((rchild, rt));
+                            }
+                        },
+                        (Some(lt), None) => stack.push((lchild, lt)),
+                        (None, Some(rt)) => stack.push((rchild, rt)),
+                        (None, None) => (),
+                    }
+                },
+            }
+        }
+        best
+    }
+
+    /// Finds the entry closest to a point.
+    ///
+    /// dist is called for each leaf that may be closer than the current best
+    /// and must return the exact distance from p to the entry. Returns the
+    /// closest entry along with its distance.
+    pub fn nearest<F>(&self, p: Point3<f32>, dist: F) -> Option<(&V, f32)>
+    where
+        F: FnMut(&V) -> f32
+    {
+        self.nearest_within(p, None, dist)
+    }
+
+    /// Finds the entry closest to a point that is no further than max_dist
+    /// away, if a maximum is given.
+    pub fn nearest_within<F>(&self, p: Point3<f32>, max_dist: Option<f32>, dist: F) -> Option<(&V, f32)>
+    where
+        F: FnMut(&V) -> f32
+    {
+        self.k_nearest(p, 1, max_dist, dist).pop()
+    }
+
+    /// Finds the k entries closest to a point that are no further than
+    /// max_dist away, if a maximum is given. Nodes are visited best first in
+    /// order of the distance from p to their bounds, and the search stops
+    /// once no unvisited node can be closer than the kth closest entry found
+    /// so far.
+    ///
+    /// dist is called for each leaf that may be among the closest and must
+    /// return the exact distance from p to the entry. Returns the entries
+    /// along with their distances sorted from nearest to furthest.
+    pub fn k_nearest<F>(
+        &self,
+        p: Point3<f32>,
+        k: usize,
+        max_dist: Option<f32>,
+        mut dist: F
+    ) -> Vec<(&V, f32)>
+    where
+        F: FnMut(&V) -> f32
+    {
+        if self.empty() || k == 0 {
+            return Vec::new();
+        }
+        let max_dist = max_dist.unwrap_or(f32::INFINITY);
+        let bound_dist = |i: usize| -> f32 {
+            (self.pool[i].bounds.closest_point(p) - p).magnitude()
+        };
+        // Nodes to visit, nearest first.
+        let mut open = BinaryHeap::new();
+        open.push(cmp::Reverse(ByDist(bound_dist(self.root), self.root)));
+        // The closest entries found so far, furthest first.
+        let mut found: BinaryHeap<ByDist<&V>> = BinaryHeap::with_capacity(k + 1);
+        while let Some(cmp::Reverse(ByDist(top_dist, top))) = open.pop() {
+            if top_dist > max_dist {
+                break;
+            }
+            if found.len() == k && found.peek().map(|f| top_dist >= f.0).unwrap_or(false) {
+                break;
+            }
+            match self.pool[top].node_type {
+                BVHNodeType::Leaf(ref val) => {
+                    let leaf_dist = dist(val);
+                    if leaf_dist <= max_dist {
+                        found.push(ByDist(leaf_dist, val));
+                        if found.len() > k {
+                            found.pop();
+                        }
+                    }
+                },
+
+                BVHNodeType::Parent(lchild, rchild) => {
+                    open.push(cmp::Reverse(ByDist(bound_dist(lchild), lchild)));
+                    open.push(cmp::Reverse(ByDist(bound_dist(rchild), rchild)));
+                }
+            }
+        }
+        found
+            .into_sorted_vec()
+            .into_iter()
+            .map(|ByDist(d, val)| (val, d))
+            .collect()
+    }
+
+    /// Finds each pair of entries, one from this BVH and one from rhs, whose
+    /// bounds overlap. callback is called once for each overlapping pair of
+    /// leaves.
+    pub fn query_pairs<V2, F>(&self, rhs: &BVH<B, V2>, callback: F)
+    where
+        F: FnMut(&V, &V2)
+    {
+        self.query_pairs_transformed(rhs, |bounds| *bounds, callback);
+    }
+
+    /// Finds each pair of distinct entries in the BVH whose bounds overlap.
+    /// The hierarchy is descended against itself, so every pair is visited
+    /// exactly once and no entry is paired with itself.
+    pub fn query_self_pairs<F>(&self, mut callback: F)
+    where
+        F: FnMut(&V, &V)
+    {
+        if self.empty() {
+            return;
+        }
+        let mut stack = SmallVec::<[(usize, usize); 64]>::new();
+        stack.push((self.root, self.root));
+        while let Some((i, j)) = stack.pop() {
+            if i == j {
+                // Pairs within a subtree are either entirely within one of its
+                // children or straddle the two.
+                if let BVHNodeType::Parent(lchild, rchild) = self.pool[i].node_type {
+                    stack.push((lchild, lchild));
+                    stack.push((rchild, rchild));
+                    stack.push((lchild, rchild));
+                }
+                continue;
+            }
+            let lhs_node = &self.pool[i];
+            let rhs_node = &self.pool[j];
+            if !lhs_node.bounds.overlaps(&rhs_node.bounds) {
+                continue;
+            }
+            match (&lhs_node.node_type, &rhs_node.node_type) {
+                (BVHNodeType::Leaf(lval), BVHNodeType::Leaf(rval)) => {
+                    callback(lval, rval);
+                },
+                (&BVHNodeType::Parent(lchild, rchild), BVHNodeType::Leaf(_)) => {
+                    stack.push((lchild, j));
+                    stack.push((rchild, j));
+                },
+                (BVHNodeType::Leaf(_), &BVHNodeType::Parent(lchild, rchild)) => {
+                    stack.push((i, lchild));
+                    stack.push((i, rchild));
+                },
+                (&BVHNodeType::Parent(llchild, lrchild),
+                 &BVHNodeType::Parent(rlchild, rrchild)) => {
+                    if lhs_node.bounds.surface_area() > rhs_node.bounds.surface_area() {
+                        stack.push((llchild, j));
+                        stack.push((lrchild, j));
+                    } else {
+                        stack.push((i, rlchild));
+                        stack.push((i, rrchild));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Finds each pair of entries, one from this BVH and one from rhs, whose
+    /// bounds overlap. Both hierarchies are descended simultaneously, always
+    /// splitting the node with the larger surface area.
+    ///
+    /// The bounds of rhs are passed through transform before being tested,
+    /// which allows the two BVHs to be stored in different frames of
+    /// reference. callback is called once for each overlapping pair of leaves.
+    pub fn query_pairs_transformed<B2, V2, T, F>(
+        &self,
+        rhs: &BVH<B2, V2>,
+        transform: T,
+        mut callback: F
+    )
+    where
+        B2: Bound,
+        T: Fn(&B2) -> B,
+        F: FnMut(&V, &V2)
+    {
+        if self.empty() || rhs.empty() {
+            return;
+        }
+        let mut stack = SmallVec::<[(usize, usize); 64]>::new();
+        stack.push((self.root, rhs.root));
+        while let Some((i, j)) = stack.pop() {
+            let lhs_node = &self.pool[i];
+            let rhs_node = &rhs.pool[j];
+            let rhs_bounds = transform(&rhs_node.bounds);
+            if !lhs_node.bounds.overlaps(&rhs_bounds) {
+                continue;
+            }
+            match (&lhs_node.node_type, &rhs_node.node_type) {
+                (BVHNodeType::Leaf(lval), BVHNodeType::Leaf(rval)) => {
+                    callback(lval, rval);
+                },
+                (&BVHNodeType::Parent(lchild, rchild), BVHNodeType::Leaf(_)) => {
+                    stack.push((lchild, j));
+                    stack.push((rchild, j));
+                },
+                (BVHNodeType::Leaf(_), &BVHNodeType::Parent(lchild, rchild)) => {
+                    stack.push((i, lchild));
+                    stack.push((i, rchild));
+                },
+                (&BVHNodeType::Parent(llchild, lrchild),
+                 &BVHNodeType::Parent(rlchild, rrchild)) => {
+                    if lhs_node.bounds.surface_area() > rhs_bounds.surface_area() {
+                        stack.push((llchild, j));
+                        stack.push((lrchild, j));
+                    } else {
+                        stack.push((i, rlchild));
+                        stack.push((i, rrchild));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Returns the surface area heuristic cost of the tree: the total surface
+    /// area of every parent node relative to the surface area of the root.
+    /// This estimates the number of nodes visited by a query, so lower is
+    /// better. An empty tree or a tree with a single leaf has a cost of zero.
+    pub fn sah_cost(&self) -> f32 {
+        if self.empty() {
+            return 0.0;
+        }
+        let root_area = self.pool[self.root].bounds.surface_area();
+        if root_area <= 0.0 {
+            return 0.0;
+        }
+        self.pool
+            .iter()
+            .filter(|(_, node)| matches!(node.node_type, BVHNodeType::Parent(_, _)))
+            .map(|(_, node)| node.bounds.surface_area())
+            .sum::<f32>() / root_area
+    }
+
+    /// Incrementally improves the quality of the tree by rotating nodes to
+    /// reduce their surface area. At most budget parent nodes are visited per
+    /// call and each call picks up where the last one left off, so calling
+    /// this once per frame with a small budget gradually repairs a tree that
+    /// has degraded from many inserts and removes.
+    ///
+    /// IDs of leaves remain valid.
+    pub fn optimize(&mut self, budget: usize) {
+        if self.empty() {
+            return;
+        }
+        let end = self.pool.upper_bound();
+        let mut visited = 0;
+        let mut scanned = 0;
+        while visited < budget && scanned < end {
+            if self.cursor >= end {
+                self.cursor = 0;
+            }
+            let i = self.cursor;
+            self.cursor += 1;
+            scanned += 1;
+            let is_parent = self.pool
+                .get(i)
+                .map(|node| matches!(node.node_type, BVHNodeType::Parent(_, _)));
+            if is_parent == Some(true) {
+                self.rotate_node(i);
+                visited += 1;
+            }
+        }
+    }
+
+    /// Performs the rotation at parent node i that most reduces the surface
+    /// area of its children, if any rotation does. A rotation swaps one child
+    /// of i with a grandchild under its other child.
+    fn rotate_node(&mut self, i: usize) {
+        let (lchild, rchild) = match self.pool[i].node_type {
+            BVHNodeType::Parent(lchild, rchild) => (lchild, rchild),
+            _ => return,
+        };
+        // Each candidate is (child to swap out, sibling, grandchild to swap in,
+        // grandchild that stays, reduction in area).
+        let mut best: Option<(usize, usize, usize, usize, f32)> = None;
+        for &(child, sibling) in [(lchild, rchild), (rchild, lchild)].iter() {
+            if let BVHNodeType::Parent(gl, gr) = self.pool[sibling].node_type {
+                let area = self.pool[sibling].bounds.surface_area();
+                for &(swap, stay) in [(gl, gr), (gr, gl)].iter() {
+                    let new_area = B::combine(&self.pool[child].bounds, &self.pool[stay].bounds)
+                        .surface_area();
+                    let reduction = area - new_area;
+                    if reduction > best.map(|b| b.4).unwrap_or(0.0) {
+                        best = Some((child, sibling, swap, stay, reduction));
+                    }
+                }
+            }
+        }
+        let (child, sibling, swap, stay, _) = match best {
+            Some(best) => best,
+            None => return,
+        };
+        self.pool[i].node_type = if child == lchild {
+            BVHNodeType::Parent(swap, sibling)
+        } else {
+            BVHNodeType::Parent(sibling, swap)
+        };
+        self.pool[sibling].node_type = BVHNodeType::Parent(child, stay);
+        self.pool[swap].parent = i;
+        self.pool[child].parent = sibling;
+        self.pool[sibling].bounds =
+            B::combine(&self.pool[child].bounds, &self.pool[stay].bounds);
+        self.pool[sibling].height =
+            1 + cmp::max(self.pool[child].height, self.pool[stay].height);
+        // The bounds of i are unchanged but heights may have changed all the
+        // way to the root.
+        let mut j = i;
+        loop {
+            if let BVHNodeType::Parent(child1, child2) = self.pool[j].node_type {
+                self.pool[j].height =
+                    1 + cmp::max(self.pool[child1].height, self.pool[child2].height);
+            }
+            if j == self.root {
+                break;
+            }
+            j = self.pool[j].parent;
+        }
+    }
+
     fn balance(&mut self, a: usize) -> usize {
         // This could be really cleaned up by using pointers instead of indices
         // everywhere.
@@ -506,8 +1150,11 @@ where
 #[cfg(test)]
 mod tests {
     mod bvh {
-        use cgmath::{Point3};
-        use crate::geom::{Sphere, AABB};
+        use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3, perspective};
+        use crate::bounds::BoundedBy;
+        use crate::frustum::Frustum;
+        use crate::collision::Intersects;
+        use crate::geom::{COLLISION_EPSILON, Ray, Sphere, AABB};
         use crate::bvh::BVH;
 
         #[test]
@@ -527,5 +1174,164 @@ mod tests {
             bvh.query(&sphere_c, |&id| { found += 1; assert_eq!(id, 3); });
             assert_eq!(found, 3);
         }
+
+        #[test]
+        fn test_build_and_refit() {
+            let mut spheres: Vec<Sphere> = (0..20)
+                .map(|i| Sphere{ c: Point3::new((i % 5) as f32 * 3.0, (i / 5) as f32 * 3.0, 0.0), r: 1.0 })
+                .collect();
+            let mut bvh: BVH<AABB, usize> = BVH::build(spheres.iter().enumerate().map(|(i, &s)| (s, i)));
+            for (i, sphere) in spheres.iter().enumerate() {
+                assert_eq!(*bvh.get_leaf(i), i);
+                let mut found = Vec::new();
+                bvh.query(sphere, |&id| found.push(id));
+                assert_eq!(found, vec![i]);
+            }
+
+            let moved = Sphere{ c: Point3::new(100.0, 0.0, 0.0), r: 1.0 };
+            bvh.update_leaf(7, &moved);
+            let mut found = Vec::new();
+            bvh.query(&moved, |&id| found.push(id));
+            assert_eq!(found, vec![7]);
+            bvh.query(&spheres[7], |_| panic!("leaf was not moved"));
+
+            for sphere in spheres.iter_mut() {
+                sphere.c.z += 10.0;
+            }
+            bvh.refit(|&i| spheres[i].bounds());
+            let mut found = 0;
+            bvh.query(&spheres[7], |&id| { found += 1; assert_eq!(id, 7); });
+            assert_eq!(found, 1);
+        }
+
+        #[test]
+        fn test_build_coincident() {
+            // Items sharing a center must still produce a balanced tree.
+            let item = AABB{ c: Point3::new(1.0, 2.0, 3.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let bvh: BVH<AABB, usize> = BVH::build((0..4096).map(|i| (item, i)));
+            assert_eq!(bvh.pool[bvh.root].height, 12);
+            let mut found = 0;
+            bvh.query(&item, |_| found += 1);
+            assert_eq!(found, 4096);
+        }
+
+        #[test]
+        fn test_k_nearest() {
+            let points: Vec<Point3<f32>> = (0..10)
+                .map(|i| Point3::new(i as f32 * 2.0, 0.0, 0.0))
+                .collect();
+            let aabbs: BVH<AABB, usize> = BVH::build(
+                points.iter().enumerate().map(|(i, &c)| (Sphere{ c, r: 0.5 }, i))
+            );
+            let spheres: BVH<Sphere, usize> = BVH::build(
+                points.iter().enumerate().map(|(i, &c)| (Sphere{ c, r: 0.5 }, i))
+            );
+            let p = Point3::new(7.2, 1.0, 0.0);
+            let dist = |&i: &usize| (points[i] - p).magnitude();
+
+            assert_eq!(*aabbs.nearest(p, dist).unwrap().0, 4);
+            assert_eq!(*spheres.nearest(p, dist).unwrap().0, 4);
+            let nearest: Vec<usize> = aabbs.k_nearest(p, 3, None, dist).iter().map(|&(&i, _)| i).collect();
+            assert_eq!(nearest, vec![4, 3, 5]);
+            let nearest: Vec<usize> = spheres.k_nearest(p, 3, None, dist).iter().map(|&(&i, _)| i).collect();
+            assert_eq!(nearest, vec![4, 3, 5]);
+            assert_eq!(aabbs.k_nearest(p, 3, Some(2.0), dist).len(), 2);
+            assert!(spheres.nearest_within(p, Some(1.0), dist).is_none());
+        }
+
+        #[test]
+        fn test_pairs() {
+            let spheres: Vec<Sphere> = (0..12)
+                .map(|i| Sphere{ c: Point3::new(i as f32 * 1.5, 0.0, 0.0), r: 1.0 })
+                .collect();
+            let mut bvh: BVH<AABB, usize> = BVH::new();
+            for (i, sphere) in spheres.iter().enumerate() {
+                bvh.insert(sphere, i);
+            }
+            let mut pairs = Vec::new();
+            bvh.query_self_pairs(|&a, &b| pairs.push((a.min(b), a.max(b))));
+            pairs.sort();
+            let expected: Vec<(usize, usize)> = (1..12).map(|i| (i - 1, i)).collect();
+            assert_eq!(pairs, expected);
+
+            let other: BVH<AABB, usize> = BVH::build(vec![
+                (Sphere{ c: Point3::new(3.0, 1.5, 0.0), r: 1.0 }, 0),
+                (Sphere{ c: Point3::new(30.0, 0.0, 0.0), r: 1.0 }, 1),
+            ]);
+            let mut pairs = Vec::new();
+            bvh.query_pairs(&other, |&a, &b| pairs.push((a, b)));
+            pairs.sort();
+            assert_eq!(pairs, vec![(1, 0), (2, 0), (3, 0)]);
+        }
+
+        #[test]
+        fn test_optimize() {
+            // Inserting in a scattered order produces a poor tree.
+            let spheres: Vec<Sphere> = (0..64)
+                .map(|i| (i * 37) % 64)
+                .map(|i| Sphere{ c: Point3::new((i % 8) as f32 * 3.0, (i / 8) as f32 * 3.0, 0.0), r: 1.0 })
+                .collect();
+            let mut bvh: BVH<AABB, usize> = BVH::new();
+            let ids: Vec<usize> = spheres.iter().enumerate().map(|(i, s)| bvh.insert(s, i)).collect();
+            let initial_cost = bvh.sah_cost();
+            for _ in 0..10 {
+                bvh.optimize(16);
+            }
+            assert!(bvh.sah_cost() < initial_cost);
+            for (i, sphere) in spheres.iter().enumerate() {
+                assert_eq!(*bvh.get_leaf(ids[i]), i);
+                let mut found = Vec::new();
+                bvh.query(sphere, |&id| found.push(id));
+                assert_eq!(found, vec![i]);
+            }
+            // The tree can still be modified after being optimized.
+            bvh.remove(ids[5]);
+            bvh.query(&spheres[5], |_| panic!("leaf was not removed"));
+        }
+
+        #[test]
+        fn test_query_frustum() {
+            let frustum = Frustum::from(
+                perspective(Deg(90.0), 1.0, 1.0, 100.0) *
+                Matrix4::look_at(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0))
+            );
+            let spheres: Vec<Sphere> = (0..20)
+                .map(|i| Sphere{ c: Point3::new(i as f32 * 4.0 - 40.0, 0.0, -20.0), r: 1.0 })
+                .collect();
+            let bvh: BVH<AABB, usize> = BVH::build(spheres.iter().enumerate().map(|(i, &s)| (s, i)));
+            let mut visible = Vec::new();
+            bvh.query_frustum(&frustum, |&i| visible.push(i));
+            visible.sort();
+            // The frustum is 40 units wide at this depth.
+            let expected: Vec<usize> = (5..16).collect();
+            assert_eq!(visible, expected);
+        }
+
+        #[test]
+        fn test_raycast_closest() {
+            let spheres = [
+                Sphere{ c: Point3::new(0.0, 5.0, 0.0), r: 1.0 },
+                Sphere{ c: Point3::new(0.0, 8.0, 0.0), r: 1.0 },
+                Sphere{ c: Point3::new(0.0, 2.0, 0.0), r: 1.0 },
+                Sphere{ c: Point3::new(3.0, 0.0, 0.0), r: 1.0 },
+            ];
+            let mut bvh: BVH<AABB, usize> = BVH::new();
+            for (i, sphere) in spheres.iter().enumerate() {
+                bvh.insert(sphere, i);
+            }
+
+            let ray = Ray{ p: Point3::new(0.0, 10.0, 0.0), d: Vector3::new(0.0, -1.0, 0.0) };
+            let mut tested = 0;
+            let (&hit, inter) = bvh.raycast_closest(&ray, |&i| {
+                tested += 1;
+                ray.intersection(&spheres[i])
+            }).unwrap();
+            assert_eq!(hit, 1);
+            assert_relative_eq!(inter.t, 1.0, epsilon = COLLISION_EPSILON);
+            assert!(tested < 3);
+
+            let ray = Ray{ p: Point3::new(0.0, 10.0, 0.0), d: Vector3::new(1.0, 0.0, 0.0) };
+            assert!(bvh.raycast_closest(&ray, |&i| ray.intersection(&spheres[i])).is_none());
+        }
     }
 }
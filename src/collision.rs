@@ -1,11 +1,15 @@
+use std::cmp;
 use std::f32;
 use std::ops::Neg;
 
 use cgmath::{EuclideanSpace, InnerSpace, Point2, Point3, Quaternion,  Rotation,
              Vector3, Zero};
+use smallvec::{smallvec, SmallVec};
 
 use crate::bitset::FixedSizeBitSet;
 use crate::geom::*;
+use crate::gjk::{closest_points, penetration_depth, rotational_time_of_impact, time_of_impact};
+use crate::mesh::ConvexMesh;
 use crate::simplex::*;
 
 /// A type that can overlap another.
@@ -67,6 +71,278 @@ impl Overlaps<Sphere> for Sphere {
     }
 }
 
+/// Implements Overlaps for a pair of types by swapping the arguments of an
+/// existing implementation.
+macro_rules! commute_overlaps {
+    (
+        $recv:ty, $arg:ty
+    ) => {
+        impl Overlaps<$arg> for $recv {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                rhs.overlaps(self)
+            }
+        }
+    };
+}
+
+/// Determines if two convex shapes are within distance r of each other.
+fn gjk_overlaps<A: Convex, B: Convex>(a: &A, b: &B, r: f32) -> bool {
+    let pts = closest_points(a, b);
+    (pts.a - pts.b).magnitude() <= r + COLLISION_EPSILON
+}
+
+/// Determines if a convex shape lies on both sides of a plane.
+fn plane_overlaps<S: Convex>(p: &Plane, s: &S) -> bool {
+    let upper = p.n.dot(s.support(p.n).to_vec());
+    let lower = p.n.dot(s.support(-p.n).to_vec());
+    lower <= p.d && p.d <= upper
+}
+
+/// A convex polytope that can be tested with the separating axis test.
+trait Separable: Convex {
+    /// Returns the normals of the faces of the polytope. For a planar polygon
+    /// this is the normal of the plane as well as the normal of each edge
+    /// within the plane.
+    fn face_normals(&self) -> SmallVec<[Vector3<f32>; 4]>;
+
+    /// Returns the directions of the edges of the polytope.
+    fn edge_dirs(&self) -> SmallVec<[Vector3<f32>; 4]>;
+}
+
+impl Separable for AABB {
+    fn face_normals(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        self.edge_dirs()
+    }
+
+    fn edge_dirs(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        smallvec![Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+    }
+}
+
+impl Separable for OBB {
+    fn face_normals(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        self.edge_dirs()
+    }
+
+    fn edge_dirs(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        smallvec![
+            self.q.rotate_vector(Vector3::unit_x()),
+            self.q.rotate_vector(Vector3::unit_y()),
+            self.q.rotate_vector(Vector3::unit_z())
+        ]
+    }
+}
+
+impl Separable for Triangle {
+    fn face_normals(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        let n = self.normal();
+        let mut normals: SmallVec<[Vector3<f32>; 4]> = smallvec![n];
+        normals.extend(self.edge_dirs().into_iter().map(|e| n.cross(e)));
+        normals
+    }
+
+    fn edge_dirs(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        smallvec![self.b - self.a, self.c - self.b, self.a - self.c]
+    }
+}
+
+impl Separable for Rectangle {
+    fn face_normals(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        smallvec![self.u[0].cross(self.u[1]), self.u[0], self.u[1]]
+    }
+
+    fn edge_dirs(&self) -> SmallVec<[Vector3<f32>; 4]> {
+        smallvec![self.u[0], self.u[1]]
+    }
+}
+
+/// Determines if two polytopes overlap by searching for a separating axis
+/// among the face normals of each and the cross products of their edges.
+fn sat_overlaps<A: Separable, B: Separable>(a: &A, b: &B) -> bool {
+    let separates = |axis: Vector3<f32>| -> bool {
+        let len2 = axis.magnitude2();
+        if len2 < COLLISION_EPSILON {
+            return false;
+        }
+        let axis = axis / len2.sqrt();
+        let a_max = axis.dot(a.support(axis).to_vec());
+        let a_min = axis.dot(a.support(-axis).to_vec());
+        let b_max = axis.dot(b.support(axis).to_vec());
+        let b_min = axis.dot(b.support(-axis).to_vec());
+        a_max < b_min || b_max < a_min
+    };
+    if a.face_normals().into_iter().any(&separates)
+        || b.face_normals().into_iter().any(&separates)
+    {
+        return false;
+    }
+    let b_edges = b.edge_dirs();
+    !a.edge_dirs()
+        .into_iter()
+        .any(|ea| b_edges.iter().any(|&eb| separates(ea.cross(eb))))
+}
+
+macro_rules! impl_sat_overlaps {
+    (
+        $recv:ty, $arg:ty
+    ) => {
+        impl Overlaps<$arg> for $recv {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                sat_overlaps(self, rhs)
+            }
+        }
+    };
+}
+
+impl_sat_overlaps!{ AABB, OBB }
+impl_sat_overlaps!{ AABB, Triangle }
+impl_sat_overlaps!{ AABB, Rectangle }
+impl_sat_overlaps!{ OBB, OBB }
+impl_sat_overlaps!{ OBB, Triangle }
+impl_sat_overlaps!{ OBB, Rectangle }
+impl_sat_overlaps!{ Triangle, Triangle }
+impl_sat_overlaps!{ Triangle, Rectangle }
+impl_sat_overlaps!{ Rectangle, Rectangle }
+commute_overlaps!{ OBB, AABB }
+commute_overlaps!{ Triangle, AABB }
+commute_overlaps!{ Rectangle, AABB }
+commute_overlaps!{ Triangle, OBB }
+commute_overlaps!{ Rectangle, OBB }
+commute_overlaps!{ Rectangle, Triangle }
+
+/// Spheres overlap shapes whose closest point lies within their radius.
+macro_rules! impl_sphere_overlaps {
+    (
+        $arg:ty
+    ) => {
+        impl Overlaps<$arg> for Sphere {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                (rhs.closest_point(self.c) - self.c).magnitude2() <= self.r * self.r
+            }
+        }
+        commute_overlaps!{ $arg, Sphere }
+    };
+}
+
+impl_sphere_overlaps!{ OBB }
+impl_sphere_overlaps!{ Triangle }
+impl_sphere_overlaps!{ Rectangle }
+
+impl Overlaps<Capsule> for Sphere {
+    fn overlaps(&self, rhs: &Capsule) -> bool {
+        let r = self.r + rhs.r;
+        (Segment::from(*rhs).closest_point(self.c) - self.c).magnitude2() <= r * r
+    }
+}
+
+impl Overlaps<Plane> for Sphere {
+    fn overlaps(&self, rhs: &Plane) -> bool {
+        (rhs.n.dot(self.c.to_vec()) - rhs.d).abs() <= self.r
+    }
+}
+
+impl Overlaps<ConvexMesh> for Sphere {
+    fn overlaps(&self, rhs: &ConvexMesh) -> bool {
+        gjk_overlaps(&self.c, rhs, self.r)
+    }
+}
+
+commute_overlaps!{ Capsule, Sphere }
+commute_overlaps!{ Plane, Sphere }
+commute_overlaps!{ ConvexMesh, Sphere }
+
+impl Overlaps<Capsule> for Capsule {
+    fn overlaps(&self, rhs: &Capsule) -> bool {
+        gjk_overlaps(&Segment::from(*self), &Segment::from(*rhs), self.r + rhs.r)
+    }
+}
+
+impl Overlaps<Plane> for Capsule {
+    fn overlaps(&self, rhs: &Plane) -> bool {
+        let da = rhs.n.dot(self.a.to_vec()) - rhs.d;
+        let db = rhs.n.dot((self.a + self.d).to_vec()) - rhs.d;
+        da * db <= 0.0 || da.abs().min(db.abs()) <= self.r
+    }
+}
+
+/// Capsules overlap shapes that lie within their radius of their segment.
+macro_rules! impl_capsule_overlaps {
+    (
+        $arg:ty
+    ) => {
+        impl Overlaps<$arg> for Capsule {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                gjk_overlaps(&Segment::from(*self), rhs, self.r)
+            }
+        }
+        commute_overlaps!{ $arg, Capsule }
+    };
+}
+
+impl_capsule_overlaps!{ AABB }
+impl_capsule_overlaps!{ OBB }
+impl_capsule_overlaps!{ Triangle }
+impl_capsule_overlaps!{ Rectangle }
+impl_capsule_overlaps!{ ConvexMesh }
+commute_overlaps!{ Plane, Capsule }
+
+impl Overlaps<Plane> for Plane {
+    /// Planes overlap unless they are parallel and distinct.
+    fn overlaps(&self, rhs: &Plane) -> bool {
+        if self.n.cross(rhs.n).magnitude2() > COLLISION_EPSILON {
+            return true;
+        }
+        if self.n.dot(rhs.n) > 0.0 {
+            (self.d - rhs.d).abs() <= COLLISION_EPSILON
+        } else {
+            (self.d + rhs.d).abs() <= COLLISION_EPSILON
+        }
+    }
+}
+
+/// Planes overlap convex shapes that lie on both of their sides.
+macro_rules! impl_plane_overlaps {
+    (
+        $arg:ty
+    ) => {
+        impl Overlaps<$arg> for Plane {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                plane_overlaps(self, rhs)
+            }
+        }
+        commute_overlaps!{ $arg, Plane }
+    };
+}
+
+impl_plane_overlaps!{ AABB }
+impl_plane_overlaps!{ OBB }
+impl_plane_overlaps!{ Triangle }
+impl_plane_overlaps!{ Rectangle }
+impl_plane_overlaps!{ ConvexMesh }
+
+/// ConvexMeshes have no faces to test, so they fall back to GJK.
+macro_rules! impl_convex_mesh_overlaps {
+    (
+        $arg:ty
+    ) => {
+        impl Overlaps<$arg> for ConvexMesh {
+            fn overlaps(&self, rhs: &$arg) -> bool {
+                gjk_overlaps(self, rhs, 0.0)
+            }
+        }
+    };
+}
+
+impl_convex_mesh_overlaps!{ AABB }
+impl_convex_mesh_overlaps!{ OBB }
+impl_convex_mesh_overlaps!{ Triangle }
+impl_convex_mesh_overlaps!{ Rectangle }
+impl_convex_mesh_overlaps!{ ConvexMesh }
+commute_overlaps!{ AABB, ConvexMesh }
+commute_overlaps!{ OBB, ConvexMesh }
+commute_overlaps!{ Triangle, ConvexMesh }
+commute_overlaps!{ Rectangle, ConvexMesh }
+
 /// A type that can completely subsume another.
 ///
 /// Contains is another common form of discrete collision detection. Most often
@@ -146,6 +422,145 @@ impl Contains<Sphere> for Sphere {
     }
 }
 
+impl Contains<Point3<f32>> for OBB {
+    fn contains(&self, p: &Point3<f32>) -> bool {
+        let d = self.q.invert().rotate_vector(p - self.c);
+        d.x.abs() <= self.r.x && d.y.abs() <= self.r.y && d.z.abs() <= self.r.z
+    }
+}
+
+impl Contains<Point3<f32>> for Capsule {
+    fn contains(&self, p: &Point3<f32>) -> bool {
+        (Segment::from(*self).closest_point(*p) - p).magnitude2() <= self.r * self.r
+    }
+}
+
+impl Contains<Point3<f32>> for ConvexMesh {
+    fn contains(&self, p: &Point3<f32>) -> bool {
+        gjk_overlaps(self, p, 0.0)
+    }
+}
+
+impl Contains<Sphere> for AABB {
+    fn contains(&self, rhs: &Sphere) -> bool {
+        (0..3).all(|i| (rhs.c[i] - self.c[i]).abs() + rhs.r <= self.r[i])
+    }
+}
+
+impl Contains<Sphere> for OBB {
+    fn contains(&self, rhs: &Sphere) -> bool {
+        let d = self.q.invert().rotate_vector(rhs.c - self.c);
+        (0..3).all(|i| d[i].abs() + rhs.r <= self.r[i])
+    }
+}
+
+impl Contains<Sphere> for Capsule {
+    fn contains(&self, rhs: &Sphere) -> bool {
+        if self.r < rhs.r {
+            return false;
+        }
+        let r = self.r - rhs.r;
+        (Segment::from(*self).closest_point(rhs.c) - rhs.c).magnitude2() <= r * r
+    }
+}
+
+/// A sphere is inside of a ConvexMesh if its center is at least its radius
+/// away from the surface, which is found with EPA.
+impl Contains<Sphere> for ConvexMesh {
+    fn contains(&self, rhs: &Sphere) -> bool {
+        match penetration_depth(self, &rhs.c) {
+            Some(pen) => pen.depth >= rhs.r,
+            None => false,
+        }
+    }
+}
+
+/// A convex polytope whose vertices can be enumerated.
+trait Polytope {
+    /// Returns true if pred is true for every vertex of the polytope.
+    fn all_vertices<F: FnMut(Point3<f32>) -> bool>(&self, pred: F) -> bool;
+}
+
+impl Polytope for AABB {
+    fn all_vertices<F: FnMut(Point3<f32>) -> bool>(&self, mut pred: F) -> bool {
+        (0..8).all(|i| {
+            let corner = Vector3::new(
+                if i & 1 == 0 { -self.r.x } else { self.r.x },
+                if i & 2 == 0 { -self.r.y } else { self.r.y },
+                if i & 4 == 0 { -self.r.z } else { self.r.z },
+            );
+            pred(self.c + corner)
+        })
+    }
+}
+
+impl Polytope for OBB {
+    fn all_vertices<F: FnMut(Point3<f32>) -> bool>(&self, mut pred: F) -> bool {
+        (0..8).all(|i| {
+            let corner = Vector3::new(
+                if i & 1 == 0 { -self.r.x } else { self.r.x },
+                if i & 2 == 0 { -self.r.y } else { self.r.y },
+                if i & 4 == 0 { -self.r.z } else { self.r.z },
+            );
+            pred(self.c + self.q.rotate_vector(corner))
+        })
+    }
+}
+
+impl Polytope for ConvexMesh {
+    fn all_vertices<F: FnMut(Point3<f32>) -> bool>(&self, mut pred: F) -> bool {
+        self.verts.iter().all(|&p| pred(p + self.x))
+    }
+}
+
+/// Convex volumes contain polytopes whose vertices they all contain.
+macro_rules! impl_polytope_contains {
+    (
+        $recv:ty, $arg:ty
+    ) => {
+        impl Contains<$arg> for $recv {
+            fn contains(&self, rhs: &$arg) -> bool {
+                rhs.all_vertices(|p| self.contains(&p))
+            }
+        }
+    };
+}
+
+impl_polytope_contains!{ Sphere, AABB }
+impl_polytope_contains!{ Sphere, OBB }
+impl_polytope_contains!{ Sphere, ConvexMesh }
+impl_polytope_contains!{ Capsule, AABB }
+impl_polytope_contains!{ Capsule, OBB }
+impl_polytope_contains!{ Capsule, ConvexMesh }
+impl_polytope_contains!{ AABB, OBB }
+impl_polytope_contains!{ AABB, ConvexMesh }
+impl_polytope_contains!{ OBB, AABB }
+impl_polytope_contains!{ OBB, OBB }
+impl_polytope_contains!{ OBB, ConvexMesh }
+impl_polytope_contains!{ ConvexMesh, AABB }
+impl_polytope_contains!{ ConvexMesh, OBB }
+impl_polytope_contains!{ ConvexMesh, ConvexMesh }
+
+/// Convex volumes contain capsules if they contain the spheres at both ends.
+macro_rules! impl_capsule_contains {
+    (
+        $recv:ty
+    ) => {
+        impl Contains<Capsule> for $recv {
+            fn contains(&self, rhs: &Capsule) -> bool {
+                self.contains(&Sphere{ c: rhs.a, r: rhs.r })
+                    && self.contains(&Sphere{ c: rhs.a + rhs.d, r: rhs.r })
+            }
+        }
+    };
+}
+
+impl_capsule_contains!{ Sphere }
+impl_capsule_contains!{ Capsule }
+impl_capsule_contains!{ AABB }
+impl_capsule_contains!{ OBB }
+impl_capsule_contains!{ ConvexMesh }
+
 /// A collision between a non-volumetric object and a volumetric object.
 #[derive(Copy, Clone, Debug)]
 pub struct Intersection {
@@ -494,26 +909,41 @@ macro_rules! commute_contacts {
 }
 
 
+/// Overlapping convex volumes produce a single contact found by EPA, which is
+/// not enough to keep a resting object from rocking. Completing the contact
+/// into a manifold is opt-in: pass it to `complete_manifold` and push the
+/// resulting points into a `ContactPruner`, which keeps at most four.
+///
+/// ```rust
+/// use mgf::cgmath::prelude::*;
+/// use mgf::cgmath::*;
+/// use mgf::*;
+///
+/// let floor = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(5.0, 1.0, 5.0), Quaternion::one());
+/// let block = OBB::new(Point3::new(0.5, 1.99, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+/// let mut pruner: ContactPruner = ContactPruner::new();
+/// floor.contacts(&block, |c| complete_manifold(&floor, &block, c, |c| {
+///     pruner.push(LocalContact {
+///         local_a: c.a + -floor.c.to_vec(),
+///         local_b: c.b + -block.c.to_vec(),
+///         global: c,
+///     })
+/// }));
+/// assert_eq!(Manifold::from(pruner).len(), 4);
+/// ```
 impl<ShapeA, ShapeB> Contacts<ShapeB> for ShapeA
 where
     ShapeA: Convex + Volumetric,
     ShapeB: Convex + Volumetric,
 {
     fn contacts<F: FnMut(Contact)>(&self, rhs: &ShapeB, mut callback: F) -> bool {
-        let d = Vector3::new(0.0, 1.0, 0.0);
-        let diff = MinkowskiDiff {
-            s1: self,
-            s2: rhs,
-        };
-        let inits: (SupportPoint, SupportPoint) = (diff.support(d), diff.support(-d));
-        let mut simp = Simplex::from(inits);
-        let min_dist = simp.closest_point_to_origin(&diff).to_vec();
-        let mag2 = min_dist.magnitude2();
-        if mag2 > COLLISION_EPSILON {
-            false
-        } else {
-            callback(simp.compute_contact(self, rhs));
-            true
+        match penetration_depth(self, rhs) {
+            // Shapes that only touch have no direction to push them apart.
+            Some(pen) if !pen.n.is_zero() => {
+                callback(Contact { a: pen.a, b: pen.b, n: pen.n, t: 0.0 });
+                true
+            },
+            _ => false,
         }
     }
 }
@@ -717,7 +1147,45 @@ impl<Poly: Polygon> Contacts<Moving<Capsule>> for Poly  {
                 }
             }
         }
-        
+
+        if v.is_zero() {
+            // Every sweep below degenerates without motion, so instead we look
+            // for the closest pair of points between the capsule's segment and
+            // the polygon. Since the segment does not pierce the polygon, one
+            // of the points is either an end point or lies on an edge.
+            let seg = Segment::from((c.a, c.a + c.d));
+            let mut best = (f32::INFINITY, c.a, c.a);
+            {
+                let mut consider = |poly_p: Point3<f32>, seg_p: Point3<f32>| {
+                    let dist = (seg_p - poly_p).magnitude2();
+                    if dist < best.0 {
+                        best = (dist, poly_p, seg_p);
+                    }
+                };
+                consider(self.closest_point(seg.a), seg.a);
+                consider(self.closest_point(seg.b), seg.b);
+                for edge_i in 0..Poly::NUM_VERTICES {
+                    let (a, b) = self.edge(edge_i);
+                    let edge = Segment::from((self.vertex(a), self.vertex(b)));
+                    if let Some((edge_p, seg_p)) = closest_pts_seg(&edge, &seg) {
+                        consider(edge_p, seg_p);
+                    }
+                    consider(edge.a, seg.closest_point(edge.a));
+                }
+            }
+            let (dist, poly_p, seg_p) = best;
+            if dist > c.r * c.r {
+                return false;
+            }
+            callback(Contact {
+                a: poly_p,
+                b: seg_p + -p.n * c.r,
+                t: 0.0,
+                n: p.n,
+            });
+            return true;
+        }
+
         // Find a contact to begin our search.
         // The following code needs to be completely re-done. 
         let start_sphere = Moving::sweep(Sphere { c: c.a, r: c.r }, v);
@@ -1086,6 +1554,245 @@ impl<Poly: Polygon> Contacts<Moving<Capsule>> for Poly  {
 }
 
 
+/// Points of a multi-point contact may lie this far from the deepest point of
+/// contact along the normal.
+const MANIFOLD_TOLERANCE: f32 = 0.005;
+
+/// Returns the vertices of the face of the box whose outward normal is most
+/// aligned with d, in winding order, along with that normal.
+fn obb_face(b: &OBB, d: Vector3<f32>) -> ([Point3<f32>; 4], Vector3<f32>) {
+    let axes = b.edge_dirs();
+    // A NaN alignment is treated as the least aligned.
+    let alignment = |i: usize| {
+        let a = axes[i].dot(d).abs();
+        if a.is_nan() { f32::NEG_INFINITY } else { a }
+    };
+    let i = (0..3)
+        .max_by(|&i, &j| alignment(i).partial_cmp(&alignment(j)).unwrap_or(cmp::Ordering::Equal))
+        .unwrap();
+    let n = if axes[i].dot(d) < 0.0 { -axes[i] } else { axes[i] };
+    let c = b.c + n * b.r[i];
+    let u = axes[(i + 1) % 3] * b.r[(i + 1) % 3];
+    let w = axes[(i + 2) % 3] * b.r[(i + 2) % 3];
+    ([c + u + w, c + u - w, c - u - w, c - u + w], n)
+}
+
+/// Clips a convex polygon against the side planes of a face, using the
+/// Sutherland-Hodgman algorithm.
+fn clip_to_face(
+    mut poly: SmallVec<[Point3<f32>; 8]>,
+    face: &[Point3<f32>],
+    n: Vector3<f32>
+) -> SmallVec<[Point3<f32>; 8]> {
+    let centroid = face.iter().fold(Vector3::zero(), |sum, p| sum + p.to_vec())
+        / face.len() as f32;
+    for i in 0..face.len() {
+        let p = face[i];
+        let mut side = (face[(i + 1) % face.len()] - p).cross(n);
+        if side.dot(centroid - p.to_vec()) > 0.0 {
+            side = -side;
+        }
+        let dist = |x: Point3<f32>| side.dot(x - p);
+        let input = poly;
+        poly = SmallVec::new();
+        for j in 0..input.len() {
+            let (x, y) = (input[j], input[(j + 1) % input.len()]);
+            let (dx, dy) = (dist(x), dist(y));
+            if dx <= 0.0 {
+                poly.push(x);
+            }
+            if (dx < 0.0 && dy > 0.0) || (dx > 0.0 && dy < 0.0) {
+                poly.push(x + (y - x) * (dx / (dx - dy)));
+            }
+        }
+    }
+    poly
+}
+
+/// Produces the contact points between a polygon and a box that are touching
+/// along n, the contact normal pointing from the polygon to the box, which is
+/// assumed to be in front of the polygon. Whichever of the polygon or the box
+/// face opposing it is most aligned with the normal serves as the reference
+/// face, which the other is clipped against.
+fn polygon_obb_manifold<Poly, F>(poly: &Poly, b: &OBB, n: Vector3<f32>, t: f32, mut callback: F) -> bool
+where
+    Poly: Polygon,
+    F: FnMut(Contact)
+{
+    let plane: Plane = (*poly).into();
+    let pn = plane.n;
+    let (box_face, box_n) = obb_face(b, -n);
+    let poly_verts: SmallVec<[Point3<f32>; 8]> =
+        (0..Poly::NUM_VERTICES).map(|i| poly.vertex(i)).collect();
+    let mut found = false;
+    if pn.dot(n) >= -box_n.dot(n) - MANIFOLD_TOLERANCE {
+        let clipped = clip_to_face(box_face.iter().cloned().collect(), &poly_verts, pn);
+        for x in clipped {
+            let depth = pn.dot(x - poly_verts[0]);
+            if depth <= MANIFOLD_TOLERANCE {
+                found = true;
+                callback(Contact { a: x + -pn * depth, b: x, n, t });
+            }
+        }
+    } else {
+        for x in clip_to_face(poly_verts, &box_face, box_n) {
+            let depth = box_n.dot(x - box_face[0]);
+            if depth <= MANIFOLD_TOLERANCE {
+                found = true;
+                callback(Contact { a: x, b: x + -box_n * depth, n, t });
+            }
+        }
+    }
+    found
+}
+
+/// Support directions used to find a feature are tilted this far away from
+/// the feature's normal.
+const FEATURE_TILT: f32 = 0.2;
+
+/// Returns the vertices of the feature of a convex shape furthest along d, in
+/// winding order. The feature is found by sampling the support function in
+/// directions tilted slightly away from d and keeping the points that remain
+/// on the supporting plane, so curved surfaces produce a single vertex.
+///
+/// The support point of a curved surface slides as the direction is tilted,
+/// while that of a flat feature stays on one of its vertices. Samples that
+/// move when the tilt is halved are rejected, however small the surface.
+fn support_feature<S: Convex>(shape: &S, d: Vector3<f32>) -> SmallVec<[Point3<f32>; 8]> {
+    let [u, w] = compute_basis(&d);
+    let extent = d.dot(shape.support(d).to_vec());
+    let mut verts: SmallVec<[Point3<f32>; 8]> = SmallVec::new();
+    for i in 0..8 {
+        let angle = i as f32 * f32::consts::FRAC_PI_4;
+        let tilt = (u * angle.cos() + w * angle.sin()) * FEATURE_TILT;
+        let p = shape.support((d + tilt).normalize());
+        let q = shape.support((d + tilt * 0.5).normalize());
+        let slide = COLLISION_EPSILON * (1.0 + p.to_vec().magnitude());
+        if (p - q).magnitude2() <= slide * slide &&
+            extent - d.dot(p.to_vec()) <= MANIFOLD_TOLERANCE &&
+            !verts.iter().any(|q| (*q - p).magnitude2() <= MANIFOLD_TOLERANCE * MANIFOLD_TOLERANCE)
+        {
+            verts.push(p);
+        }
+    }
+    verts
+}
+
+/// Completes a single contact between two convex shapes, such as one found
+/// by EPA, into a manifold covering the whole region of contact. The feature
+/// of each shape furthest along the contact normal is found and the smaller
+/// feature is clipped against the face of the other.
+///
+/// The closure is called for every point in the manifold, or once with the
+/// original contact if either feature is a single vertex or both are edges.
+/// Two faces can produce more than four points, so the points are meant to
+/// be pushed into a `ContactPruner`, which reduces them to four.
+pub fn complete_manifold<A, B, F>(a: &A, b: &B, contact: Contact, mut callback: F)
+where
+    A: Convex,
+    B: Convex,
+    F: FnMut(Contact)
+{
+    let n = contact.n;
+    let t = contact.t;
+    let fa = support_feature(a, n);
+    let fb = support_feature(b, -n);
+    let mut points: SmallVec<[Contact; 8]> = SmallVec::new();
+    if fa.len() >= 2 && fb.len() >= 2 {
+        if fa.len() >= 3 && fa.len() >= fb.len() {
+            for x in clip_to_face(fb, &fa, n) {
+                let depth = n.dot(x - fa[0]);
+                if depth <= MANIFOLD_TOLERANCE {
+                    points.push(Contact { a: x + -n * depth, b: x, n, t });
+                }
+            }
+        } else if fb.len() >= 3 {
+            for x in clip_to_face(fa, &fb, -n) {
+                let depth = -n.dot(x - fb[0]);
+                if depth <= MANIFOLD_TOLERANCE {
+                    points.push(Contact { a: x, b: x + n * depth, n, t });
+                }
+            }
+        }
+    }
+    // Clipping an edge produces each of its end points twice.
+    points.dedup_by(|p, q| (p.b - q.b).magnitude2() <= MANIFOLD_TOLERANCE * MANIFOLD_TOLERANCE);
+    if points.len() < 2 {
+        callback(contact);
+    } else {
+        points.into_iter().for_each(callback);
+    }
+}
+
+impl Contacts<Moving<OBB>> for Plane {
+    fn contacts<F: FnMut(Contact)>(&self, obb: &Moving<OBB>, mut callback: F) -> bool {
+        let &Moving(b, v) = obb;
+        let dist = self.n.dot(b.c.to_vec()) - self.d;
+        let r = b.edge_dirs()
+            .iter()
+            .zip([b.r.x, b.r.y, b.r.z].iter())
+            .map(|(u, e)| self.n.dot(*u).abs() * e)
+            .sum::<f32>();
+        let (t, side) = if dist.abs() <= r {
+            (0.0, if dist < 0.0 { -1.0 } else { 1.0 })
+        } else {
+            let denom = self.n.dot(v);
+            if denom * dist >= 0.0 {
+                return false;
+            }
+            let r = if dist > 0.0 { r } else { -r };
+            let t = (r - dist) / denom;
+            if t > 1.0 {
+                return false;
+            }
+            (t, r.signum())
+        };
+        // Every vertex that has reached the plane is a point of contact.
+        let b = b + v * t;
+        b.all_vertices(|p| {
+            let dist = (self.n.dot(p.to_vec()) - self.d) * side;
+            if dist <= MANIFOLD_TOLERANCE {
+                let q = p + -self.n * (dist * side);
+                callback(Contact { a: q, b: p, n: self.n, t });
+            }
+            true
+        });
+        true
+    }
+}
+
+impl<Poly: Polygon + Convex> Contacts<Moving<OBB>> for Poly {
+    fn contacts<F: FnMut(Contact)>(&self, obb: &Moving<OBB>, mut callback: F) -> bool {
+        let &Moving(b, v) = obb;
+        let mut contact = match time_of_impact(self, &b, v) {
+            Some(contact) => contact,
+            None => return false,
+        };
+        let plane: Plane = (*self).into();
+        if contact.n.dot(plane.n) < 0.0 {
+            // Polygons are one sided: boxes reaching the back of one pass
+            // through it, and boxes already overlapping it are pushed out the
+            // front.
+            if contact.t > 0.0 {
+                return false;
+            }
+            contact = match polygon_contact(&b, self) {
+                Some(contact) if !contact.n.is_zero() => -contact,
+                _ => return false,
+            };
+        }
+        let moved = b + v * contact.t;
+        if !polygon_obb_manifold(self, &moved, contact.n, contact.t, &mut callback) {
+            callback(contact);
+        }
+        true
+    }
+}
+
+commute_contacts!{ Moving<OBB>, Plane }
+commute_contacts!{ Moving<OBB>, Triangle }
+commute_contacts!{ Moving<OBB>, Rectangle }
+
 impl Contacts<Moving<Sphere>> for Sphere {
     fn contacts<F: FnMut(Contact)>(&self, sphere: &Moving<Sphere>, mut callback: F) -> bool {
         let &Moving(s, v) = sphere;
@@ -1365,6 +2072,193 @@ fn clamp(n: f32, min: f32, max: f32) -> f32 {
     }
 }
 
+/// A convex shape inflated by a radius.
+struct Rounded<S>(S, f32);
+
+impl<S: Convex> Convex for Rounded<S> {
+    fn support(&self, axis: Vector3<f32>) -> Point3<f32> {
+        self.0.support(axis) + axis * self.1
+    }
+}
+
+/// A volume that can be swept with conservative advancement.
+trait SweptHull {
+    type Hull: Convex;
+
+    /// Calls the closure with the convex hull of the volume.
+    fn with_hull<R, F: FnOnce(&Self::Hull) -> R>(&self, f: F) -> R;
+}
+
+macro_rules! impl_swept_hull {
+    (
+        $shape:ty
+    ) => {
+        impl SweptHull for $shape {
+            type Hull = $shape;
+
+            fn with_hull<R, F: FnOnce(&Self::Hull) -> R>(&self, f: F) -> R {
+                f(self)
+            }
+        }
+    };
+}
+
+impl_swept_hull!{ Sphere }
+impl_swept_hull!{ AABB }
+impl_swept_hull!{ OBB }
+impl_swept_hull!{ ConvexMesh }
+impl_swept_hull!{ Triangle }
+impl_swept_hull!{ Rectangle }
+
+impl SweptHull for Capsule {
+    type Hull = Rounded<Segment>;
+
+    fn with_hull<R, F: FnOnce(&Self::Hull) -> R>(&self, f: F) -> R {
+        f(&Rounded(Segment::from(*self), self.r))
+    }
+}
+
+/// Continuous collision between volumes without a hand-written sweep is found
+/// through conservative advancement.
+macro_rules! impl_swept_contacts {
+    (
+        $recv:ty, $arg:ty
+    ) => {
+        impl Contacts<Moving<$arg>> for $recv {
+            fn contacts<F: FnMut(Contact)>(&self, rhs: &Moving<$arg>, mut callback: F) -> bool {
+                let contact = self.with_hull(|a| {
+                    rhs.0.with_hull(|b| time_of_impact(a, b, rhs.1))
+                });
+                if let Some(contact) = contact {
+                    callback(contact);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    };
+}
+
+impl_swept_contacts!{ Sphere, AABB }
+impl_swept_contacts!{ Sphere, OBB }
+impl_swept_contacts!{ Sphere, ConvexMesh }
+impl_swept_contacts!{ Capsule, AABB }
+impl_swept_contacts!{ Capsule, OBB }
+impl_swept_contacts!{ Capsule, ConvexMesh }
+impl_swept_contacts!{ AABB, Sphere }
+impl_swept_contacts!{ AABB, Capsule }
+impl_swept_contacts!{ AABB, AABB }
+impl_swept_contacts!{ AABB, OBB }
+impl_swept_contacts!{ AABB, ConvexMesh }
+impl_swept_contacts!{ OBB, Sphere }
+impl_swept_contacts!{ OBB, Capsule }
+impl_swept_contacts!{ OBB, AABB }
+impl_swept_contacts!{ OBB, OBB }
+impl_swept_contacts!{ OBB, ConvexMesh }
+impl_swept_contacts!{ ConvexMesh, Sphere }
+impl_swept_contacts!{ ConvexMesh, Capsule }
+impl_swept_contacts!{ ConvexMesh, AABB }
+impl_swept_contacts!{ ConvexMesh, OBB }
+impl_swept_contacts!{ ConvexMesh, ConvexMesh }
+
+/// Finds the discrete contact between a volume and a polygon by treating the
+/// polygon as a degenerate convex shape. Polygons are one sided, so if the
+/// shallowest way out of the polygon is through its back the volume is pushed
+/// out of its front instead, from the point on the volume deepest below it.
+fn polygon_contact<S, P>(shape: &S, poly: &P) -> Option<Contact>
+where
+    S: Convex,
+    P: Polygon + Convex,
+{
+    let pen = penetration_depth(shape, poly)?;
+    let plane: Plane = (*poly).into();
+    if pen.n.dot(plane.n) <= 0.0 {
+        return Some(Contact { a: pen.a, b: pen.b, n: pen.n, t: 0.0 });
+    }
+    let deepest = shape.support(-plane.n);
+    let depth = plane.d - plane.n.dot(deepest.to_vec());
+    Some(Contact {
+        a: deepest,
+        b: deepest + plane.n * depth,
+        n: -plane.n,
+        t: 0.0,
+    })
+}
+
+/// Volumes collide discretely with polygons through polygon_contact.
+macro_rules! impl_polygon_contacts {
+    (
+        $recv:ty: $( $poly:ty ),*
+    ) => {
+        $(
+            impl Contacts<$poly> for $recv {
+                fn contacts<F: FnMut(Contact)>(&self, rhs: &$poly, mut callback: F) -> bool {
+                    if let Some(contact) = self.with_hull(|a| polygon_contact(a, rhs)) {
+                        callback(contact);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+
+            commute_contacts!{ $poly, $recv }
+        )*
+    };
+}
+
+impl_polygon_contacts!{ Sphere: Triangle, Rectangle }
+impl_polygon_contacts!{ Capsule: Triangle, Rectangle }
+impl_polygon_contacts!{ AABB: Triangle, Rectangle }
+impl_polygon_contacts!{ OBB: Triangle, Rectangle }
+impl_polygon_contacts!{ ConvexMesh: Triangle, Rectangle }
+
+/// Continuous collision with rotating volumes is found through conservative
+/// advancement bounded by the speed of the furthest point from the axis of
+/// rotation.
+macro_rules! impl_rotating_contacts {
+    (
+        $recv:ty: $( $arg:ty ),*
+    ) => {
+        $(
+            impl Contacts<Rotating<$arg>> for $recv {
+                fn contacts<F: FnMut(Contact)>(&self, rhs: &Rotating<$arg>, mut callback: F) -> bool {
+                    let &Rotating(ref geom, v, omega) = rhs;
+                    let c = geom.center();
+                    let contact = self.with_hull(|a| {
+                        geom.with_hull(|b| rotational_time_of_impact(a, b, c, v, omega))
+                    });
+                    if let Some(contact) = contact {
+                        callback(contact);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_rotating_contacts!{ Triangle: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ Rectangle: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ Sphere: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ Capsule: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ AABB: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ OBB: Sphere, Capsule, AABB, OBB, ConvexMesh }
+impl_rotating_contacts!{ ConvexMesh: Sphere, Capsule, AABB, OBB, ConvexMesh }
+
+impl<Recv, Arg> Contacts<Arg> for Rotating<Recv>
+where
+    Recv: Shape,
+    Arg: Contacts<Rotating<Recv>>
+{
+    fn contacts<F: FnMut(Contact)>(&self, rhs: &Arg, mut callback: F) -> bool {
+        rhs.contacts(self, |c| callback(-c))
+    }
+}
+
 impl<Recv, Arg> Contacts<Arg> for Moving<Recv>
 where
     Arg: Shape + Copy,
@@ -1812,12 +2706,43 @@ mod tests {
                 assert_eq!(c.n, Vector3::new(0.0, 1.0, 0.0));
             }));
         }
+
+        #[test]
+        fn test_embedded_tri_collision() {
+            use crate::geom::AABB;
+            let floor = Triangle {
+                a: Vector3::new(-2.0, 0.0, -2.0),
+                b: Vector3::new(-2.0, 0.0, 2.0),
+                c: Vector3::new(2.0, 0.0, -2.0),
+            };
+            // Volumes sunk past the middle of a one sided floor are still
+            // pushed out of its front.
+            for &y in [0.2, -0.2, -0.5].iter() {
+                let sphere = Sphere{ c: Point3::new(-0.5, y, -0.5), r: 1.0 };
+                let mut contacts = Vec::new();
+                assert!(floor.contacts(&sphere, |c| contacts.push(c)));
+                assert_eq!(contacts.len(), 1);
+                let c = contacts[0];
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!((c.a - c.b).dot(c.n), 1.0 - y, epsilon = 0.001);
+                assert_relative_eq!(c.a.y, 0.0, epsilon = 0.001);
+                assert!(floor.contacts(&Moving::sweep(sphere, Vector3::new(0.0, 0.0, 0.0)), |c| {
+                    assert_eq!(c.n, Vector3::new(0.0, 1.0, 0.0));
+                }));
+            }
+            let aabb = AABB{ c: Point3::new(-0.5, -0.5, -0.5), r: Vector3::new(1.0, 1.0, 1.0) };
+            let mut contacts = Vec::new();
+            assert!(floor.contacts(&aabb, |c| contacts.push(c)));
+            assert_relative_eq!(contacts[0].n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+            assert_relative_eq!((contacts[0].a - contacts[0].b).dot(contacts[0].n), 1.5, epsilon = 0.001);
+        }
     }
 
     mod obbs {
-        use cgmath::{Point3, Vector3, Quaternion, One};
-        use crate::geom::OBB;
-        use crate::collision::{Contacts, Contact};
+        use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, Quaternion, One};
+        use crate::geom::{Capsule, Moving, OBB, Plane, Rect, Sphere};
+        use crate::collision::{complete_manifold, Contacts, Contact, LocalContact};
+        use crate::manifold::{ContactPruner, Manifold};
 
         #[test]
         fn test_obb_collision() {
@@ -1841,12 +2766,218 @@ mod tests {
             assert_eq!(collision.a.y, 1.0);
             assert_eq!(collision.b.y, 0.30000007);
         }
+
+        #[test]
+        fn test_touching_obbs() {
+            let box1 = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+            // Touching, barely overlapping, barely apart and overlapping.
+            for &y in [2.0, 2.0 - 1.0e-6, 2.0 + 1.0e-6, 1.999].iter() {
+                let box2 = OBB::new(Point3::new(0.3, y, -0.2), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+                let collision = box1.last_contact(&box2).unwrap();
+                assert_relative_eq!(collision.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!((collision.a - collision.b).dot(collision.n), 2.0 - y, epsilon = 0.00001);
+            }
+            let box2 = OBB::new(Point3::new(0.3, 2.1, -0.2), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+            assert!(box1.last_contact(&box2).is_none());
+        }
+
+        #[test]
+        fn test_obb_manifold() {
+            let floor = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(5.0, 1.0, 5.0), Quaternion::one());
+            let crate_ = OBB::new(Point3::new(0.5, 1.99, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+            let contact = floor.last_contact(&crate_).unwrap();
+            let mut pruner: ContactPruner = ContactPruner::new();
+            complete_manifold(&floor, &crate_, contact, |c| {
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!(c.a.y, 1.0, epsilon = 0.001);
+                assert_relative_eq!(c.b.y, 0.99, epsilon = 0.001);
+                pruner.push(LocalContact {
+                    local_a: c.a + -floor.c.to_vec(),
+                    local_b: c.b + -crate_.c.to_vec(),
+                    global: c,
+                });
+            });
+            assert_eq!(Manifold::from(pruner).len(), 4);
+
+            // A sphere resting on the floor only touches at one point, no
+            // matter how small it is.
+            for &r in [1.0, 0.1, 0.01].iter() {
+                let ball = Sphere{ c: Point3::new(0.0, 1.0 + r * 0.9, 0.0), r };
+                let contact = floor.last_contact(&ball).unwrap();
+                let mut count = 0;
+                complete_manifold(&floor, &ball, contact, |_| count += 1);
+                assert_eq!(count, 1);
+            }
+            // Nor does a small capsule standing upright.
+            let pole = Capsule{ a: Point3::new(0.0, 1.09, 0.0), d: Vector3::new(0.0, 1.0, 0.0), r: 0.1 };
+            let contact = floor.last_contact(&pole).unwrap();
+            let mut count = 0;
+            complete_manifold(&floor, &pole, contact, |_| count += 1);
+            assert_eq!(count, 1);
+        }
+
+        #[test]
+        fn test_moving_obb_collision() {
+            let wall = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.05, 2.0, 2.0), Quaternion::one());
+            let bullet = OBB::new(Point3::new(-5.0, 0.0, 0.0), Vector3::new(0.5, 0.5, 0.5), Quaternion::one());
+            // Discretely the bullet would pass straight through the wall.
+            let collision: Contact = wall.last_contact(&Moving::sweep(bullet, Vector3::new(10.0, 0.0, 0.0))).unwrap();
+            assert_relative_eq!(collision.t, 0.445, epsilon = 0.001);
+            assert_relative_eq!(collision.n, Vector3::new(-1.0, 0.0, 0.0), epsilon = 0.001);
+            let collision: Contact = Moving::sweep(bullet, Vector3::new(10.0, 0.0, 0.0)).last_contact(&wall).unwrap();
+            assert_relative_eq!(collision.a.x, -0.05, epsilon = 0.001);
+            let capsule = Capsule{ a: Point3::new(-3.0, 0.0, 0.0), d: Vector3::new(0.0, 1.0, 0.0), r: 0.5 };
+            let collision: Contact = wall.last_contact(&Moving::sweep(capsule, Vector3::new(10.0, 0.0, 0.0))).unwrap();
+            assert_relative_eq!(collision.t, 0.245, epsilon = 0.001);
+            assert!(wall.last_contact(&Moving::sweep(capsule, Vector3::new(0.0, 10.0, 0.0))).is_none());
+        }
+
+        #[test]
+        fn test_obb_polygon_collision() {
+            let floor = Rect {
+                c: Point3::new(0.0, 0.0, 0.0),
+                u: [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)],
+                e: [3.0, 3.0],
+            };
+            let fall = Vector3::new(0.0, -4.0, 0.0);
+            let flat = OBB::new(Point3::new(0.0, 3.0, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+            let mut contacts: Vec<Contact> = Vec::new();
+            assert!(floor.contacts(&Moving::sweep(flat, fall), |c| contacts.push(c)));
+            assert_eq!(contacts.len(), 4);
+            for c in contacts.iter() {
+                assert_relative_eq!(c.t, 0.5, epsilon = 0.001);
+                assert_relative_eq!(c.a.y, 0.0, epsilon = 0.001);
+                assert_relative_eq!(c.b.y, 0.0, epsilon = 0.005);
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+            }
+            contacts.clear();
+            // Hanging over the edge of the floor, two corners land and the
+            // other two contacts are clipped to the edge.
+            let overhang = OBB::new(Point3::new(3.5, 3.0, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one());
+            assert!(floor.contacts(&Moving::sweep(overhang, fall), |c| contacts.push(c)));
+            assert_eq!(contacts.len(), 4);
+            for c in contacts.iter() {
+                assert!(c.a.x <= 3.0 + 0.001);
+            }
+            contacts.clear();
+            // Balanced on a corner, the box lands on a single point.
+            let q = Quaternion::from_arc(
+                Vector3::new(1.0, 1.0, 1.0).normalize(), Vector3::new(0.0, 1.0, 0.0), None
+            );
+            let corner = OBB::new(Point3::new(0.0, 3.0, 0.0), Vector3::new(1.0, 1.0, 1.0), q);
+            assert!(floor.contacts(&Moving::sweep(corner, fall), |c| contacts.push(c)));
+            assert_eq!(contacts.len(), 1);
+            assert_relative_eq!(contacts[0].a, Point3::new(0.0, 0.0, 0.0), epsilon = 0.01);
+            contacts.clear();
+
+            let plane = Plane{ n: Vector3::new(0.0, 1.0, 0.0), d: 0.0 };
+            assert!(Moving::sweep(flat, fall).contacts(&plane, |c| contacts.push(c)));
+            assert_eq!(contacts.len(), 4);
+            for c in contacts.iter() {
+                assert_eq!(c.t, 0.5);
+                assert_eq!(c.n, Vector3::new(0.0, -1.0, 0.0));
+            }
+            assert!(!plane.contacts(&Moving::sweep(flat, -fall), |_| ()));
+            // A NaN direction must not panic when picking the face.
+            super::super::obb_face(&flat, Vector3::new(f32::NAN, 0.0, 0.0));
+        }
+    }
+
+    mod overlaps {
+        use cgmath::{Deg, Point3, Quaternion, Rotation3, Vector3};
+        use crate::collision::Overlaps;
+        use crate::geom::*;
+        use crate::mesh::ConvexMesh;
+
+        #[test]
+        fn test_overlaps() {
+            let aabb = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            // Rotated 45 degrees about two axes so that only an edge cross
+            // product separates it from the AABB.
+            let rot = Quaternion::from_angle_y(Deg(45.0)) * Quaternion::from_angle_x(Deg(45.0));
+            let obb = OBB::new(Point3::new(2.2, 2.2, 0.0), Vector3::new(1.0, 1.0, 1.0), rot);
+            assert!(!aabb.overlaps(&obb));
+            assert!(aabb.overlaps(&OBB{ c: Point3::new(1.5, 1.5, 0.0), ..obb }));
+
+            let tri = Triangle::from((Point3::new(0.5, 1.5, 0.0), Point3::new(1.5, 0.5, 0.0), Point3::new(2.0, 2.0, 0.0)));
+            assert!(aabb.overlaps(&tri));
+            assert!(!tri.overlaps(&(aabb + Vector3::new(-0.5, -0.5, 0.0))));
+            let rect = Rectangle::new(Point3::new(0.0, 0.0, 1.5), [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)]);
+            assert!(!rect.overlaps(&aabb));
+            assert!(!(rect + Vector3::new(0.0, 0.0, -0.5)).overlaps(&tri));
+            assert!(rect.overlaps(&Triangle::from((Point3::new(0.0, 0.0, 1.0), Point3::new(1.0, 0.0, 2.0), Point3::new(0.0, 1.0, 2.0)))));
+
+            let capsule = Capsule{ a: Point3::new(-3.0, 1.5, 0.0), d: Vector3::new(6.0, 0.0, 0.0), r: 0.6 };
+            assert!(capsule.overlaps(&aabb));
+            assert!(!Capsule{ r: 0.4, ..capsule }.overlaps(&aabb));
+            assert!(capsule.overlaps(&Sphere{ c: Point3::new(0.0, 2.5, 0.0), r: 0.5 }));
+            assert!(capsule.overlaps(&Capsule{ a: Point3::new(0.0, 2.5, -1.0), d: Vector3::new(0.0, 0.0, 2.0), r: 0.5 }));
+            assert!(!capsule.overlaps(&Capsule{ a: Point3::new(0.0, 3.0, -1.0), d: Vector3::new(0.0, 0.0, 2.0), r: 0.5 }));
+
+            let plane = Plane::new(Vector3::new(0.0, 1.0, 0.0), 0.9);
+            assert!(plane.overlaps(&aabb));
+            assert!(plane.overlaps(&tri));
+            assert!(plane.overlaps(&rect));
+            assert!(!Plane::new(Vector3::new(0.0, 1.0, 0.0), 1.1).overlaps(&rect));
+            assert!(capsule.overlaps(&plane));
+            assert!(!plane.overlaps(&Plane::new(Vector3::new(0.0, -1.0, 0.0), 0.9)));
+            assert!(plane.overlaps(&Plane::new(Vector3::new(0.0, -1.0, 0.0), -0.9)));
+
+            let hull = ConvexMesh::from(vec![
+                Point3::new(3.0, 0.0, 0.0), Point3::new(4.0, 0.0, 0.0),
+                Point3::new(3.0, 1.0, 0.0), Point3::new(3.0, 0.0, 1.0),
+            ]);
+            assert!(!hull.overlaps(&aabb));
+            assert!(hull.overlaps(&(aabb + Vector3::new(2.5, 0.0, 0.0))));
+            assert!(Sphere{ c: Point3::new(2.0, 0.5, 0.5), r: 1.1 }.overlaps(&hull));
+            assert!(!Sphere{ c: Point3::new(2.0, 0.5, 0.5), r: 0.9 }.overlaps(&hull));
+            assert!(plane.overlaps(&hull));
+        }
+    }
+
+    mod contains {
+        use cgmath::{Deg, Point3, Quaternion, Rotation3, Vector3};
+        use crate::collision::Contains;
+        use crate::geom::*;
+        use crate::mesh::ConvexMesh;
+
+        #[test]
+        fn test_contains() {
+            let obb = OBB::new(Point3::new(5.0, 0.0, 0.0), Vector3::new(2.0, 1.0, 1.0), Quaternion::from_angle_z(Deg(90.0)));
+            assert!(obb.contains(&Point3::new(5.5, 1.5, 0.0)));
+            assert!(!obb.contains(&Point3::new(6.5, 0.0, 0.0)));
+            let capsule = Capsule{ a: Point3::new(0.0, 0.0, 0.0), d: Vector3::new(0.0, 4.0, 0.0), r: 1.0 };
+            assert!(capsule.contains(&Point3::new(0.5, 4.5, 0.0)));
+            assert!(!capsule.contains(&Point3::new(0.9, 4.9, 0.0)));
+
+            let cube: Vec<Point3<f32>> = (0..8)
+                .map(|i| Point3::new((i & 1) as f32 * 2.0, ((i >> 1) & 1) as f32 * 2.0, (i >> 2) as f32 * 2.0))
+                .collect();
+            let hull = ConvexMesh::from(cube);
+            assert!(hull.contains(&Point3::new(1.0, 1.0, 1.0)));
+            assert!(!hull.contains(&Point3::new(1.0, 1.0, 2.5)));
+            assert!(hull.contains(&Sphere{ c: Point3::new(1.0, 1.0, 1.0), r: 0.9 }));
+            assert!(!hull.contains(&Sphere{ c: Point3::new(1.0, 1.0, 0.5), r: 0.9 }));
+            assert!(hull.contains(&AABB{ c: Point3::new(1.0, 1.0, 1.0), r: Vector3::new(0.5, 0.5, 0.5) }));
+            assert!(hull.contains(&Capsule{ a: Point3::new(1.0, 0.5, 1.0), d: Vector3::new(0.0, 1.0, 0.0), r: 0.4 }));
+            assert!(!hull.contains(&Capsule{ a: Point3::new(1.0, 0.5, 1.0), d: Vector3::new(0.0, 1.0, 0.0), r: 0.6 }));
+
+            let aabb = AABB{ c: Point3::new(5.0, 0.0, 0.0), r: Vector3::new(3.0, 3.0, 3.0) };
+            assert!(aabb.contains(&obb));
+            assert!(!obb.contains(&aabb));
+            assert!(aabb.contains(&Sphere{ c: Point3::new(7.0, 0.0, 0.0), r: 1.0 }));
+            assert!(!aabb.contains(&Sphere{ c: Point3::new(7.5, 0.0, 0.0), r: 1.0 }));
+            assert!(Sphere{ c: Point3::new(5.0, 0.0, 0.0), r: 2.5 }.contains(&obb));
+            assert!(!Sphere{ c: Point3::new(5.0, 0.0, 0.0), r: 2.0 }.contains(&obb));
+            assert!(capsule.contains(&Capsule{ a: Point3::new(0.0, 1.0, 0.0), d: Vector3::new(0.0, 2.0, 0.5), r: 0.5 }));
+            assert!(!capsule.contains(&Capsule{ a: Point3::new(0.0, 1.0, 0.0), d: Vector3::new(0.0, 2.0, 0.0), r: 1.5 }));
+        }
     }
 
     mod capsules {
         use cgmath::{Point3, Vector3};
         use crate::geom;
-        use crate::geom::{Capsule, Sphere, Moving, Triangle, Rect};
+        use crate::geom::{Capsule, Sphere, Moving, Rotating, Triangle, Rect};
         use crate::collision::{Contacts, Contact};
 
         #[test]
@@ -2266,6 +3397,31 @@ mod tests {
             assert_eq!(contacts.len(), 1);
             contacts.clear();
         }
+
+        #[test]
+        fn test_rotating_capsule_collision() {
+            // A thin wall in the x = 0 plane.
+            let wall = Triangle::from((
+                Point3::new(0.0, -10.0, -10.0),
+                Point3::new(0.0, 10.0, -10.0),
+                Point3::new(0.0, 0.0, 10.0),
+            ));
+            // A capsule thrown end over end at the wall. Its center stops
+            // short of the wall, but its tip passes through it.
+            let capsule = Capsule {
+                a: Point3::new(-2.0, -1.5, 0.0),
+                d: Vector3::new(0.0, 3.0, 0.0),
+                r: 0.1,
+            };
+            let v = Vector3::new(1.0, 0.0, 0.0);
+            let spin = Vector3::new(0.0, 0.0, -std::f32::consts::PI);
+            assert!(wall.last_contact(&Moving::sweep(capsule, v)).is_none());
+            let collision: Contact = wall.last_contact(&Rotating::sweep(capsule, v, spin)).unwrap();
+            assert!(collision.t > 0.0 && collision.t < 1.0);
+            assert_relative_eq!(collision.n, Vector3::new(-1.0, 0.0, 0.0), epsilon = 0.01);
+            let collision: Contact = Rotating::sweep(capsule, v, spin).last_contact(&wall).unwrap();
+            assert_relative_eq!(collision.n, Vector3::new(1.0, 0.0, 0.0), epsilon = 0.01);
+        }
     }        
 }
         
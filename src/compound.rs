@@ -25,6 +25,7 @@ use crate::bvh::*;
 use crate::bounds::*;
 use crate::collision::*;
 use crate::geom::*;
+use crate::mesh::{ConvexMesh, Mesh};
 
 /// A component is a generic volume that can either be a Sphere or Capsule at
 /// runtime. Anything that can collide with a Sphere and a Capsule can collide
@@ -176,6 +177,9 @@ impl_component_collision!{ Triangle }
 impl_component_collision!{ Rectangle }
 impl_component_collision!{ Sphere }
 impl_component_collision!{ Capsule }
+impl_component_collision!{ AABB }
+impl_component_collision!{ OBB }
+impl_component_collision!{ ConvexMesh }
 
 impl<RHS> Contacts<RHS> for Moving<Component>
 where
@@ -243,11 +247,8 @@ pub struct Compound {
 
 impl Compound {
     pub fn new(components: Vec<Component>) -> Self {
-        let mut bvh: BVH<AABB, Component> = BVH::new();
-        let mut shapes: SmallVec<[usize; 1]> = SmallVec::with_capacity(components.len());
-        for component in components.iter() {
-            shapes.push(bvh.insert(component, *component));
-        }
+        let shapes: SmallVec<[usize; 1]> = (0..components.len()).collect();
+        let bvh: BVH<AABB, Component> = BVH::build(components.iter().map(|&c| (c, c)));
         Compound {
             disp: Vector3::zero(),
             rot: Quaternion::one(),
@@ -312,22 +313,9 @@ impl<P: Particle> Intersects<Compound> for P {
         let p = conj_rot.rotate_point(self.pos() + -rhs.disp) + rhs.disp;
         let d = conj_rot.rotate_vector(self.dir());
         let r = Ray{ p, d };
-        let mut result: Option<Intersection> = None;
-        rhs.bvh.raytrace(&r, |&comp, inter| {
-            if inter.t > P::DT {
-                return;
-            }
-            let shape = comp.rotate(rhs.rot) + rhs.disp;
-            if let Some(inter) = self.intersection(&shape) {
-                if let Some(res) = result {
-                    if inter.t > res.t {
-                        return;
-                    }
-                }
-                result = Some(inter)
-            }
-        });
-        result
+        rhs.bvh
+            .raycast_closest(&r, |&comp| self.intersection(&(comp.rotate(rhs.rot) + rhs.disp)))
+            .map(|(_, inter)| inter)
     }
 }
 
@@ -351,6 +339,75 @@ where
     }
 }
 
+/// Finds the contacts between two components that are already in the same
+/// frame of reference. The point a of each contact lies on lhs.
+fn component_contacts<F: FnMut(Contact)>(lhs: &Component, rhs: &Component, callback: F) -> bool {
+    let rhs = Moving::sweep(*rhs, Vector3::zero());
+    match *lhs {
+        Component::Sphere(ref s) => s.contacts(&rhs, callback),
+        Component::Capsule(ref c) => c.contacts(&rhs, callback),
+    }
+}
+
+impl Contacts<Compound> for Compound {
+    fn contacts<F: FnMut(Contact)>(&self, rhs: &Compound, mut callback: F) -> bool {
+        // Move the bounds of rhs into the local space of self so that neither
+        // BVH needs to be rebuilt.
+        let conj_rot = self.rot.conjugate();
+        let rel_rot = conj_rot * rhs.rot;
+        let rel_disp = conj_rot.rotate_vector(rhs.disp - self.disp);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let mut collided = false;
+        self.bvh.query_pairs_transformed(
+            &rhs.bvh,
+            |bounds| bounds.rotate_about(rel_rot, origin) + rel_disp,
+            |&lcomp, &rcomp| {
+                let lshape = lcomp.rotate_about(self.rot, origin) + self.disp;
+                let rshape = rcomp.rotate_about(rhs.rot, origin) + rhs.disp;
+                if component_contacts(&lshape, &rshape, &mut callback) {
+                    collided = true;
+                }
+            }
+        );
+        collided
+    }
+}
+
+impl Contacts<Mesh> for Compound {
+    fn contacts<F: FnMut(Contact)>(&self, rhs: &Mesh, mut callback: F) -> bool {
+        let conj_rot = self.rot.conjugate();
+        let rel_disp = rhs.x - self.disp;
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let mut collided = false;
+        self.bvh.query_pairs_transformed(
+            &rhs.bvh,
+            |bounds| (*bounds + rel_disp).rotate_about(conj_rot, origin),
+            |&comp, &face_index| {
+                let shape = comp.rotate_about(self.rot, origin) + self.disp;
+                let tri = match rhs.collision_face(face_index, shape.center()) {
+                    Some(tri) => tri,
+                    None => return,
+                };
+                let shape = Moving::sweep(shape, Vector3::zero());
+                tri.contacts(&shape, |c| {
+                    collided = true;
+                    callback(-Contact {
+                        n: rhs.correct_normal(face_index, c.a, c.n),
+                        ..c
+                    });
+                });
+            }
+        );
+        collided
+    }
+}
+
+impl Contacts<Compound> for Mesh {
+    fn contacts<F: FnMut(Contact)>(&self, rhs: &Compound, mut callback: F) -> bool {
+        rhs.contacts(self, |c| callback(-c))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     mod compound {
@@ -386,5 +443,54 @@ mod tests {
 
             let _contact: Contact = compound.last_contact(&Moving::sweep(static_rect, Vector3::new(0.0, 3.0, 0.0))).unwrap();
         }
+
+        #[test]
+        fn test_compound_compound() {
+            let mut lhs = Compound::new(vec![
+                Component::Sphere(Sphere{ c: Point3::new(1.0, 0.0, 0.0), r: 1.0 }),
+            ]);
+            let mut rhs = Compound::new(vec![
+                Component::Sphere(Sphere{ c: Point3::new(1.0, 0.0, 0.0), r: 1.0 }),
+                Component::Sphere(Sphere{ c: Point3::new(-5.0, 0.0, 0.0), r: 1.0 }),
+            ]);
+            // Rotate rhs so that its first component ends up at (3.5, 0, 0).
+            rhs.rot = Quaternion::from_arc(Vector3::new(1.0, 0.0, 0.0),
+                                           Vector3::new(0.0, 1.0, 0.0),
+                                           None).normalize();
+            rhs.disp = Vector3::new(3.5, -1.0, 0.0);
+            assert!(!lhs.contacts(&rhs, |c: Contact| { panic!("c = {:?}", c); }));
+
+            lhs.disp = Vector3::new(1.0, 0.0, 0.0);
+            let contact: Contact = lhs.last_contact(&rhs).unwrap();
+            assert_relative_eq!(contact.a, Point3::new(3.0, 0.0, 0.0), epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(contact.b, Point3::new(2.5, 0.0, 0.0), epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(contact.n, Vector3::new(1.0, 0.0, 0.0), epsilon = COLLISION_EPSILON);
+        }
+
+        #[test]
+        fn test_compound_mesh() {
+            let mut mesh = Mesh::new();
+            mesh.push_vert(Point3::new(-5.0, 0.0, -5.0));
+            mesh.push_vert(Point3::new(-5.0, 0.0, 5.0));
+            mesh.push_vert(Point3::new(5.0, 0.0, 5.0));
+            mesh.push_vert(Point3::new(5.0, 0.0, -5.0));
+            mesh.push_face((0, 1, 2));
+            mesh.push_face((0, 2, 3));
+            mesh.x = Vector3::new(0.0, -1.0, 0.0);
+
+            let mut compound = Compound::new(vec![
+                Component::Capsule(Capsule{ a: Point3::new(0.0, 0.0, 0.0), d: Vector3::new(2.0, 0.0, 0.0), r: 0.5 }),
+            ]);
+            assert!(!compound.contacts(&mesh, |c: Contact| { panic!("c = {:?}", c); }));
+
+            // Stand the capsule up so that it pokes through the floor.
+            compound.rot = Quaternion::from_arc(Vector3::new(1.0, 0.0, 0.0),
+                                                Vector3::new(0.0, -1.0, 0.0),
+                                                None).normalize();
+            let contact: Contact = compound.last_contact(&mesh).unwrap();
+            assert_relative_eq!(contact.n, Vector3::new(0.0, -1.0, 0.0), epsilon = COLLISION_EPSILON);
+            let contact: Contact = mesh.last_contact(&compound).unwrap();
+            assert_relative_eq!(contact.n, Vector3::new(0.0, 1.0, 0.0), epsilon = COLLISION_EPSILON);
+        }
     }
 }
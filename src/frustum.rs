@@ -0,0 +1,179 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector4};
+
+use crate::collision::{Contains, Overlaps};
+use crate::geom::{AABB, Plane, Sphere};
+
+use serde::{Serialize, Deserialize};
+
+/// How much of an object lies within a volume.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Containment {
+    /// The object lies entirely outside of the volume.
+    Outside,
+    /// The object lies partially inside of the volume.
+    Intersects,
+    /// The object lies entirely inside of the volume.
+    Inside,
+}
+
+/// A type that can determine how much of another object it contains.
+///
+/// Classify is conservative: an object reported as intersecting may in rare
+/// cases lie just outside of the volume, but objects reported as inside or
+/// outside are always so.
+pub trait Classify<RHS> {
+    /// Returns how much of the argument lies within the current object.
+    fn classify(&self, rhs: &RHS) -> Containment;
+}
+
+/// A viewing volume bounded by six planes whose normals point inward.
+#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct Frustum {
+    /// The left, right, bottom, top, near and far planes, in that order.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Returns the signed distance from p to each plane in turn.
+    fn plane_dists(&self, p: Point3<f32>) -> [f32; 6] {
+        let mut dists = [0.0; 6];
+        for (dist, plane) in dists.iter_mut().zip(self.planes.iter()) {
+            *dist = plane.n.dot(p.to_vec()) - plane.d;
+        }
+        dists
+    }
+}
+
+impl From<Matrix4<f32>> for Frustum {
+    /// Extracts the planes of the frustum from a view-projection matrix with
+    /// the Gribb-Hartmann method. The matrix is expected to map the frustum
+    /// to OpenGL style clip space, as the projections in cgmath do.
+    fn from(m: Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let plane = |v: Vector4<f32>| {
+            let n = v.truncate();
+            let len = n.magnitude();
+            Plane{ n: n / len, d: -v.w / len }
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                plane(w + x),
+                plane(w - x),
+                plane(w + y),
+                plane(w - y),
+                plane(w + z),
+                plane(w - z),
+            ],
+        }
+    }
+}
+
+impl Classify<AABB> for Frustum {
+    fn classify(&self, rhs: &AABB) -> Containment {
+        let mut result = Containment::Inside;
+        for (plane, dist) in self.planes.iter().zip(self.plane_dists(rhs.c).iter()) {
+            let r = plane.n.x.abs() * rhs.r.x + plane.n.y.abs() * rhs.r.y + plane.n.z.abs() * rhs.r.z;
+            if *dist < -r {
+                return Containment::Outside;
+            }
+            if *dist < r {
+                result = Containment::Intersects;
+            }
+        }
+        result
+    }
+}
+
+impl Classify<Sphere> for Frustum {
+    fn classify(&self, rhs: &Sphere) -> Containment {
+        let mut result = Containment::Inside;
+        for dist in self.plane_dists(rhs.c).iter() {
+            if *dist < -rhs.r {
+                return Containment::Outside;
+            }
+            if *dist < rhs.r {
+                result = Containment::Intersects;
+            }
+        }
+        result
+    }
+}
+
+impl Contains<Point3<f32>> for Frustum {
+    fn contains(&self, p: &Point3<f32>) -> bool {
+        self.plane_dists(*p).iter().all(|&dist| dist >= 0.0)
+    }
+}
+
+macro_rules! impl_frustum_bound {
+    ($bound:ty) => {
+        impl Overlaps<$bound> for Frustum {
+            fn overlaps(&self, rhs: &$bound) -> bool {
+                self.classify(rhs) != Containment::Outside
+            }
+        }
+
+        impl Contains<$bound> for Frustum {
+            fn contains(&self, rhs: &$bound) -> bool {
+                self.classify(rhs) == Containment::Inside
+            }
+        }
+    };
+}
+
+impl_frustum_bound!{ AABB }
+impl_frustum_bound!{ Sphere }
+
+#[cfg(test)]
+mod tests {
+    mod frustum {
+        use cgmath::{Deg, Matrix4, Point3, Vector3, perspective};
+        use crate::collision::*;
+        use crate::frustum::*;
+        use crate::geom::{AABB, Sphere};
+
+        #[test]
+        fn test_frustum() {
+            let proj = perspective(Deg(90.0), 1.0, 1.0, 100.0);
+            let view = Matrix4::look_at(
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(0.0, 0.0, -1.0),
+                Vector3::new(0.0, 1.0, 0.0)
+            );
+            let frustum = Frustum::from(proj * view);
+            assert!(frustum.contains(&Point3::new(0.0, 0.0, -10.0)));
+            assert!(!frustum.contains(&Point3::new(0.0, 0.0, 10.0)));
+            assert!(!frustum.contains(&Point3::new(20.0, 0.0, -10.0)));
+
+            let inside = AABB{ c: Point3::new(0.0, 0.0, -10.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let crossing = AABB{ c: Point3::new(10.0, 0.0, -10.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let outside = AABB{ c: Point3::new(0.0, 0.0, -200.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            assert_eq!(frustum.classify(&inside), Containment::Inside);
+            assert_eq!(frustum.classify(&crossing), Containment::Intersects);
+            assert_eq!(frustum.classify(&outside), Containment::Outside);
+            assert!(frustum.overlaps(&crossing));
+            assert!(!frustum.contains(&crossing));
+
+            assert_eq!(frustum.classify(&Sphere{ c: Point3::new(0.0, 5.0, -10.0), r: 1.0 }), Containment::Inside);
+            assert_eq!(frustum.classify(&Sphere{ c: Point3::new(0.0, 0.0, -0.5), r: 1.0 }), Containment::Intersects);
+            assert_eq!(frustum.classify(&Sphere{ c: Point3::new(0.0, 0.0, 3.0), r: 1.0 }), Containment::Outside);
+        }
+    }
+}
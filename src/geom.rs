@@ -16,7 +16,7 @@
 use std::f32;
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
-use cgmath::{EuclideanSpace, InnerSpace, Rotation, Point3, Rotation3, Vector3, Quaternion};
+use cgmath::{EuclideanSpace, InnerSpace, Rad, Rotation, Point3, Rotation3, Vector3, Quaternion};
 
 use crate::collision;
 
@@ -394,6 +394,92 @@ impl<T: Shape> Delta for Moving<T> {
     }
 }
 
+/// An object with a position at the start of the time step.
+pub trait Positioned {
+    fn position(&self) -> Point3<f32>;
+}
+
+impl<T: Shape> Positioned for T {
+    fn position(&self) -> Point3<f32> {
+        self.center()
+    }
+}
+
+impl<T: Shape> Positioned for Moving<T> {
+    fn position(&self) -> Point3<f32> {
+        self.0.center()
+    }
+}
+
+/// A geometry swept across a given path of motion while rotating about its
+/// center.
+///
+/// The angular displacement is the axis of rotation scaled by the angle, in
+/// radians, that the object turns over the time step.
+#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct Rotating<T: Shape>(pub T, pub Vector3<f32>, pub Vector3<f32>);
+
+impl<T: Copy + Clone + Shape> Rotating<T> {
+    /// Create a rotating object with velocity of vel and angular displacement
+    /// of omega.
+    pub fn sweep(obj: T, vel: Vector3<f32>, omega: Vector3<f32>) -> Self {
+        Rotating(obj, vel, omega)
+    }
+}
+
+impl<T: Shape> Rotating<T> {
+    /// Return the velocity of the object.
+    pub fn vel(&self) -> Vector3<f32> {
+        self.1
+    }
+
+    /// Return the angular displacement of the object.
+    pub fn omega(&self) -> Vector3<f32> {
+        self.2
+    }
+
+    /// Return the rotation of the object at time t.
+    pub fn rotation(&self, t: f32) -> Quaternion<f32> {
+        let angle = self.2.magnitude() * t;
+        if angle == 0.0 {
+            Quaternion::new(1.0, 0.0, 0.0, 0.0)
+        } else {
+            Quaternion::from_axis_angle(self.2.normalize(), Rad(angle))
+        }
+    }
+}
+
+impl<T: Shape> From<Moving<T>> for Rotating<T> {
+    fn from(m: Moving<T>) -> Self {
+        Rotating(m.0, m.1, Vector3::new(0.0, 0.0, 0.0))
+    }
+}
+
+impl<T: Shape> AsRef<T> for Rotating<T> {
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Shape> AsMut<T> for Rotating<T> {
+    fn as_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Shape> Delta for Rotating<T> {
+    fn delta(&self) -> Vector3<f32> {
+        self.1
+    }
+}
+
+impl<T: Shape> Positioned for Rotating<T> {
+    fn position(&self) -> Point3<f32> {
+        self.0.center()
+    }
+}
+
 
 fn clamp(n: f32, min: f32, max: f32) -> f32 {
     if n < min {
@@ -730,12 +816,12 @@ impl Shape for OBB {
     }
 
     fn closest_point(&self, to: Point3<f32>) -> Point3<f32> {
-        let to = self.q.invert().rotate_point(to);
-        self.q.rotate_point(
-            Point3::new(
-                clamp(to.x, self.c.x - self.r.x, self.c.x + self.r.x),
-                clamp(to.y, self.c.y - self.r.y, self.c.y + self.r.y),
-                clamp(to.z, self.c.z - self.r.z, self.c.z + self.r.z),
+        let d = self.q.invert().rotate_vector(to - self.c);
+        self.c + self.q.rotate_vector(
+            Vector3::new(
+                clamp(d.x, -self.r.x, self.r.x),
+                clamp(d.y, -self.r.y, self.r.y),
+                clamp(d.z, -self.r.z, self.r.z),
             )
         )
     }
@@ -750,8 +836,12 @@ impl Shape for Sphere {
 
     fn closest_point(&self, to: Point3<f32>) -> Point3<f32> {
         let d = to - self.c;
-        let rat = d.magnitude2() / (self.r * self.r);
-        self.c + d * rat
+        let dist2 = d.magnitude2();
+        if dist2 <= self.r * self.r {
+            to
+        } else {
+            self.c + d * (self.r / dist2.sqrt())
+        }
     }
 }
 
@@ -1053,20 +1143,53 @@ impl Convex for Sphere {
     }
 }
 
+impl Convex for Point3<f32> {
+    fn support(&self, _: Vector3<f32>) -> Point3<f32> {
+        *self
+    }
+}
+
+impl Convex for Segment {
+    fn support(&self, d: Vector3<f32>) -> Point3<f32> {
+        if d.dot(self.b - self.a) > 0.0 {
+            self.b
+        } else {
+            self.a
+        }
+    }
+}
+
+impl Convex for Triangle {
+    fn support(&self, d: Vector3<f32>) -> Point3<f32> {
+        let (da, db, dc) = (d.dot(self.a), d.dot(self.b), d.dot(self.c));
+        Point3::from_vec(if da >= db && da >= dc {
+            self.a
+        } else if db >= dc {
+            self.b
+        } else {
+            self.c
+        })
+    }
+}
+
+impl Convex for Rectangle {
+    fn support(&self, d: Vector3<f32>) -> Point3<f32> {
+        self.c
+            + self.u[0] * (d.dot(self.u[0]).signum() * self.e[0])
+            + self.u[1] * (d.dot(self.u[1]).signum() * self.e[1])
+    }
+}
+
 impl Convex for Capsule {
+    /// The support of a capsule is the end of its segment furthest along d,
+    /// pushed out by the radius along d.
     fn support(&self, d: Vector3<f32>) -> Point3<f32> {
-        // I have yet to find an implementation of this that I believe to be
-        // correct, so for now I'm just going to use the version I found
-        // for a cylinder and add the radius to the end.
         use cgmath::Zero;
-        let c = self.a + self.d * 0.5;
-        let u = self.d.normalize();
-        let ud = u.dot(d);
-        let w = d - u * ud;
-        if w.is_zero() {
-            c + (self.d.magnitude() * 0.5 + self.r) * u * ud.signum()
+        let end = if self.d.dot(d) < 0.0 { self.a } else { self.a + self.d };
+        if d.is_zero() {
+            end
         } else {
-            c + (self.d.magnitude() * 0.5 + self.r) * u * ud.signum() + w.normalize() * self.r
+            end + d.normalize() * self.r
         }
     }
 }
@@ -1168,7 +1291,10 @@ mod tests {
         #[test]
         fn test_capsule_support_fn() {
             let capsule = Capsule::new(Segment::new(Point3::new(2.0, 0.0, 0.0), Point3::new(4.0, 0.0, 0.0)), 1.0);
-            assert_eq!(capsule.support(Vector3::new(0.0, 1.0, 0.0)), Point3::new(5.0, 1.0, 0.0));
+            // Perpendicular to the segment the support lies on the rounded
+            // surface, not on a cylinder's rim.
+            assert_eq!(capsule.support(Vector3::new(0.0, 1.0, 0.0)), Point3::new(4.0, 1.0, 0.0));
+            assert_eq!(capsule.support(Vector3::new(1.0, 1.0, 0.0)), Point3::new(4.0 + 0.5f32.sqrt(), 0.5f32.sqrt(), 0.0));
             assert_eq!(capsule.support(Vector3::new(-1.0, 0.0, 0.0)), Point3::new(1.0, 0.0, 0.0));
         }
     }
@@ -0,0 +1,884 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use cgmath::prelude::*;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation, Rotation3, Vector3};
+
+use crate::collision::Contact;
+use crate::geom::*;
+use crate::mpr::mpr_penetration;
+
+/// Maximum number of support points GJK will evaluate before settling for the
+/// best answer found so far.
+const MAX_ITERATIONS: usize = 64;
+
+/// Conservative advancement stops once the shapes are within this distance of
+/// each other.
+const TOI_TOLERANCE: f32 = 0.001;
+
+/// GJK terminates once a new support point improves the squared distance by
+/// less than this fraction.
+const REL_TOLERANCE: f32 = 0.00001;
+
+/// The set of vertices on the Minkowski difference currently used by GJK,
+/// along with the barycentric coordinates of the point closest to the origin.
+#[derive(Copy, Clone, Debug)]
+struct GjkSimplex {
+    verts: [SupportPoint; 4],
+    bary: [f32; 4],
+    len: usize,
+}
+
+/// A sub-simplex given by indices into a GjkSimplex and barycentric weights for
+/// each of them.
+#[derive(Copy, Clone)]
+struct Region {
+    idx: [usize; 4],
+    bary: [f32; 4],
+    len: usize,
+}
+
+impl Region {
+    fn vertex(i: usize) -> Self {
+        Region { idx: [i, 0, 0, 0], bary: [1.0, 0.0, 0.0, 0.0], len: 1 }
+    }
+
+    fn closest(&self, p: &[Vector3<f32>; 4]) -> Vector3<f32> {
+        (0..self.len).fold(Vector3::zero(), |v, i| v + p[self.idx[i]] * self.bary[i])
+    }
+}
+
+fn closest_on_edge(p: &[Vector3<f32>; 4], i: usize, j: usize) -> Region {
+    let ab = p[j] - p[i];
+    let t = -p[i].dot(ab);
+    if t <= 0.0 {
+        return Region::vertex(i);
+    }
+    let denom = ab.dot(ab);
+    if t >= denom {
+        return Region::vertex(j);
+    }
+    let t = t / denom;
+    Region { idx: [i, j, 0, 0], bary: [1.0 - t, t, 0.0, 0.0], len: 2 }
+}
+
+fn closest_on_face(p: &[Vector3<f32>; 4], i: usize, j: usize, k: usize) -> Region {
+    // This is the triangle closest point routine from Real-Time Collision
+    // Detection, with the query point fixed at the origin.
+    let (a, b, c) = (p[i], p[j], p[k]);
+    let ab = b - a;
+    let ac = c - a;
+    let d1 = ab.dot(-a);
+    let d2 = ac.dot(-a);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return Region::vertex(i);
+    }
+
+    let d3 = ab.dot(-b);
+    let d4 = ac.dot(-b);
+    if d3 >= 0.0 && d4 <= d3 {
+        return Region::vertex(j);
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return Region { idx: [i, j, 0, 0], bary: [1.0 - v, v, 0.0, 0.0], len: 2 };
+    }
+
+    let d5 = ab.dot(-c);
+    let d6 = ac.dot(-c);
+    if d6 >= 0.0 && d5 <= d6 {
+        return Region::vertex(k);
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return Region { idx: [i, k, 0, 0], bary: [1.0 - w, w, 0.0, 0.0], len: 2 };
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return Region { idx: [j, k, 0, 0], bary: [1.0 - w, w, 0.0, 0.0], len: 2 };
+    }
+
+    let sum = va + vb + vc;
+    if sum <= f32::EPSILON * ab.magnitude2() * ac.magnitude2() {
+        // The triangle is degenerate, so the answer lies on one of its edges.
+        return closest_of(p, &[
+            closest_on_edge(p, i, j),
+            closest_on_edge(p, i, k),
+            closest_on_edge(p, j, k),
+        ]);
+    }
+    let v = vb / sum;
+    let w = vc / sum;
+    Region { idx: [i, j, k, 0], bary: [1.0 - v - w, v, w, 0.0], len: 3 }
+}
+
+fn closest_on_volume(p: &[Vector3<f32>; 4]) -> Region {
+    const FACES: [(usize, usize, usize, usize); 4] = [
+        (0, 1, 2, 3), (0, 2, 3, 1), (0, 3, 1, 2), (1, 3, 2, 0)
+    ];
+    let ab = p[1] - p[0];
+    let ac = p[2] - p[0];
+    let ad = p[3] - p[0];
+    let vol = ab.cross(ac).dot(ad);
+    if vol.abs() <= f32::EPSILON * ab.magnitude() * ac.magnitude() * ad.magnitude() {
+        // A flat tetrahedron has no interior, so test each of its faces.
+        return closest_of(p, &[
+            closest_on_face(p, 0, 1, 2),
+            closest_on_face(p, 0, 2, 3),
+            closest_on_face(p, 0, 3, 1),
+            closest_on_face(p, 1, 3, 2),
+        ]);
+    }
+    let mut best: Option<Region> = None;
+    let mut best_dist = f32::INFINITY;
+    for &(i, j, k, l) in FACES.iter() {
+        let n = (p[j] - p[i]).cross(p[k] - p[i]);
+        // Only faces that separate the origin from the opposing vertex can
+        // contain the closest point.
+        if n.dot(-p[i]) * n.dot(p[l] - p[i]) >= 0.0 {
+            continue;
+        }
+        let region = closest_on_face(p, i, j, k);
+        let dist = region.closest(p).magnitude2();
+        if dist < best_dist {
+            best = Some(region);
+            best_dist = dist;
+        }
+    }
+    if let Some(region) = best {
+        return region;
+    }
+    // The origin is enclosed by the tetrahedron.
+    let wb = (-p[0]).cross(ac).dot(ad) / vol;
+    let wc = ab.cross(-p[0]).dot(ad) / vol;
+    let wd = ab.cross(ac).dot(-p[0]) / vol;
+    Region { idx: [0, 1, 2, 3], bary: [1.0 - wb - wc - wd, wb, wc, wd], len: 4 }
+}
+
+fn closest_of(p: &[Vector3<f32>; 4], regions: &[Region]) -> Region {
+    let mut best = 0;
+    let mut best_dist = regions[0].closest(p).magnitude2();
+    for (i, region) in regions.iter().enumerate().skip(1) {
+        let dist = region.closest(p).magnitude2();
+        if dist < best_dist {
+            best = i;
+            best_dist = dist;
+        }
+    }
+    regions[best]
+}
+
+impl GjkSimplex {
+    fn new(p: SupportPoint) -> Self {
+        GjkSimplex {
+            verts: [p; 4],
+            bary: [1.0, 0.0, 0.0, 0.0],
+            len: 1,
+        }
+    }
+
+    fn push(&mut self, p: SupportPoint) {
+        self.verts[self.len] = p;
+        self.bary[self.len] = 0.0;
+        self.len += 1;
+    }
+
+    /// Returns true if p is already a vertex of the simplex.
+    fn has_vertex(&self, p: &SupportPoint) -> bool {
+        self.verts[..self.len]
+            .iter()
+            .any(|v| (v.p - p.p).magnitude2() <= COLLISION_EPSILON * COLLISION_EPSILON)
+    }
+
+    /// Reduces the simplex to the smallest sub-simplex containing the point
+    /// closest to the origin and returns that point.
+    fn solve(&mut self) -> Vector3<f32> {
+        let mut p = [Vector3::zero(); 4];
+        for (p, v) in p.iter_mut().zip(self.verts[..self.len].iter()) {
+            *p = v.p.to_vec();
+        }
+        let region = match self.len {
+            1 => Region::vertex(0),
+            2 => closest_on_edge(&p, 0, 1),
+            3 => closest_on_face(&p, 0, 1, 2),
+            _ => closest_on_volume(&p),
+        };
+        let verts = self.verts;
+        for i in 0..region.len {
+            self.verts[i] = verts[region.idx[i]];
+            self.bary[i] = region.bary[i];
+        }
+        self.len = region.len;
+        self.closest()
+    }
+
+    /// Returns the point on the simplex closest to the origin.
+    fn closest(&self) -> Vector3<f32> {
+        (0..self.len).fold(Vector3::zero(), |v, i| v + self.verts[i].p.to_vec() * self.bary[i])
+    }
+
+    /// Returns the points on each shape that correspond to the point closest to
+    /// the origin.
+    fn witnesses(&self) -> (Point3<f32>, Point3<f32>) {
+        let (a, b) = (0..self.len).fold(
+            (Vector3::zero(), Vector3::zero()),
+            |(a, b), i| (a + self.verts[i].a.to_vec() * self.bary[i],
+                         b + self.verts[i].b.to_vec() * self.bary[i])
+        );
+        (Point3::from_vec(a), Point3::from_vec(b))
+    }
+}
+
+/// Runs GJK on the Minkowski difference of two shapes, returning the final
+/// simplex. The simplex encloses the origin if the shapes overlap.
+fn gjk<S: Convex<SupportPoint>>(diff: &S, axis: Vector3<f32>) -> GjkSimplex {
+    let axis = if axis.magnitude2() > COLLISION_EPSILON {
+        axis.normalize()
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let mut simplex = GjkSimplex::new(diff.support(-axis));
+    let mut v = simplex.solve();
+    for _ in 0..MAX_ITERATIONS {
+        let vv = v.magnitude2();
+        if simplex.len == 4 || vv <= COLLISION_EPSILON {
+            break;
+        }
+        let w = diff.support(-v / vv.sqrt());
+        // Stop once the new support point no longer gets us any closer.
+        if vv - v.dot(w.p.to_vec()) <= vv * REL_TOLERANCE || simplex.has_vertex(&w) {
+            break;
+        }
+        simplex.push(w);
+        v = simplex.solve();
+    }
+    simplex
+}
+
+/// The pair of points closest to each other on two convex shapes.
+#[derive(Copy, Clone, Debug)]
+pub struct ClosestPoints {
+    /// The point on the first shape.
+    pub a: Point3<f32>,
+    /// The point on the second shape.
+    pub b: Point3<f32>,
+    /// The distance between the two points, which is zero if the shapes
+    /// overlap.
+    pub dist: f32,
+    /// The unit vector pointing from a to b, which is a separating axis of
+    /// the two shapes. Zero if the shapes overlap.
+    pub axis: Vector3<f32>,
+    /// The number of distinct vertices of each shape spanning the feature the
+    /// closest point lies on: one for a vertex, two for an edge and three or
+    /// more for a face.
+    pub features: (usize, usize),
+}
+
+/// Finds the closest pair of points between two convex shapes using the
+/// Gilbert-Johnson-Keerthi algorithm. If the shapes overlap, both points lie
+/// at the same location inside of each shape.
+pub fn closest_points<A, B>(a: &A, b: &B) -> ClosestPoints
+where
+    A: Convex,
+    B: Convex,
+{
+    closest_points_from(a, b, Vector3::zero())
+}
+
+/// Finds the closest pair of points between two convex shapes, starting the
+/// search from the given axis. Passing the axis returned by the previous
+/// query of the same shapes lets GJK converge in fewer iterations when the
+/// shapes have only moved slightly.
+pub fn closest_points_from<A, B>(a: &A, b: &B, axis: Vector3<f32>) -> ClosestPoints
+where
+    A: Convex,
+    B: Convex,
+{
+    let diff = MinkowskiDiff { s1: a, s2: b };
+    // GJK starts by searching along the opposite of the axis it is given.
+    let simplex = gjk(&diff, -axis);
+    let (a, b) = simplex.witnesses();
+    let dist = (b - a).magnitude();
+    let axis = if simplex.len < 4 && dist > COLLISION_EPSILON {
+        (b - a) / dist
+    } else {
+        Vector3::zero()
+    };
+    let verts = &simplex.verts[..simplex.len];
+    let distinct = |point: fn(&SupportPoint) -> Point3<f32>| -> usize {
+        (0..verts.len())
+            .filter(|&i| {
+                verts[..i].iter().all(|v| {
+                    (point(v) - point(&verts[i])).magnitude2() > COLLISION_EPSILON
+                })
+            })
+            .count()
+    };
+    let features = (distinct(|v| v.a), distinct(|v| v.b));
+    ClosestPoints {
+        a,
+        b,
+        dist: if axis.is_zero() { 0.0 } else { dist },
+        axis,
+        features,
+    }
+}
+
+/// The deepest point of overlap between two convex shapes.
+#[derive(Copy, Clone, Debug)]
+pub struct PenetrationDepth {
+    /// The point on the first shape furthest inside of the second.
+    pub a: Point3<f32>,
+    /// The point on the second shape furthest inside of the first.
+    pub b: Point3<f32>,
+    /// Direction of the minimum translation. Moving the second shape by depth
+    /// along n separates the two shapes.
+    pub n: Vector3<f32>,
+    /// The distance the shapes overlap by.
+    pub depth: f32,
+}
+
+/// Maximum number of faces the expanding polytope may be grown by before EPA
+/// settles for the closest face found so far.
+const MAX_EPA_ITERATIONS: usize = 64;
+
+/// EPA terminates once a new support point lies less than this distance
+/// beyond the closest face of the polytope.
+const EPA_TOLERANCE: f32 = 0.0001;
+
+/// A face of the polytope built by EPA, given by indices into its vertices.
+#[derive(Copy, Clone)]
+struct EpaFace {
+    idx: [usize; 3],
+    n: Vector3<f32>,
+    dist: f32,
+}
+
+impl EpaFace {
+    /// Creates a face with a normal following the winding of the vertices,
+    /// or returns None if the face is degenerate.
+    fn new(verts: &[SupportPoint], idx: [usize; 3]) -> Option<Self> {
+        let a = verts[idx[0]].p;
+        let n = (verts[idx[1]].p - a).cross(verts[idx[2]].p - a);
+        let len = n.magnitude();
+        if len <= f32::EPSILON {
+            return None;
+        }
+        let n = n / len;
+        Some(EpaFace { idx, n, dist: n.dot(a.to_vec()) })
+    }
+}
+
+/// Grows a simplex that GJK reduced to fewer than four vertices around the
+/// origin into a tetrahedron. Returns false if the Minkowski difference is
+/// flat and no tetrahedron exists.
+fn blow_up<S: Convex<SupportPoint>>(diff: &S, simplex: &mut GjkSimplex) -> bool {
+    let axes = [
+        Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z(),
+        -Vector3::unit_x(), -Vector3::unit_y(), -Vector3::unit_z(),
+    ];
+    if simplex.len == 1 {
+        let p = simplex.verts[0].p;
+        match axes.iter().map(|&axis| diff.support(axis)).find(|w| (w.p - p).magnitude2() > COLLISION_EPSILON) {
+            Some(w) => simplex.push(w),
+            None => return false,
+        }
+    }
+    if simplex.len == 2 {
+        let (p, e) = (simplex.verts[0].p, simplex.verts[1].p - simplex.verts[0].p);
+        let found = axes[..3]
+            .iter()
+            .map(|&axis| e.cross(axis))
+            .filter(|d| d.magnitude2() > COLLISION_EPSILON)
+            .flat_map(|d| vec![d.normalize(), -d.normalize()])
+            .map(|d| diff.support(d))
+            .find(|w| e.cross(w.p - p).magnitude2() > COLLISION_EPSILON * e.magnitude2());
+        match found {
+            Some(w) => simplex.push(w),
+            None => return false,
+        }
+    }
+    if simplex.len == 3 {
+        let p = simplex.verts[0].p;
+        let n = (simplex.verts[1].p - p).cross(simplex.verts[2].p - p);
+        if n.magnitude2() <= f32::EPSILON {
+            return false;
+        }
+        let n = n.normalize();
+        let w = diff.support(n);
+        let w = if n.dot(w.p - p) > COLLISION_EPSILON { w } else { diff.support(-n) };
+        if n.dot(w.p - p).abs() <= COLLISION_EPSILON {
+            return false;
+        }
+        simplex.push(w);
+    }
+    true
+}
+
+/// Finds the penetration of two overlapping shapes with the Expanding Polytope
+/// Algorithm, starting from a simplex produced by GJK that encloses the
+/// origin.
+fn epa<S: Convex<SupportPoint>>(diff: &S, mut simplex: GjkSimplex) -> Option<PenetrationDepth> {
+    if simplex.len < 4 && !blow_up(diff, &mut simplex) {
+        // Both shapes are flat and coplanar, so they only touch.
+        let (a, b) = simplex.witnesses();
+        return Some(PenetrationDepth { a, b, n: Vector3::zero(), depth: 0.0 });
+    }
+    let mut verts: Vec<SupportPoint> = simplex.verts.to_vec();
+    let centroid = verts.iter().fold(Vector3::zero(), |c, v| c + v.p.to_vec()) / 4.0;
+    let mut faces: Vec<EpaFace> = Vec::new();
+    for &idx in [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]].iter() {
+        let face = EpaFace::new(&verts, idx)?;
+        // Orient every face away from the interior of the tetrahedron.
+        if face.n.dot(verts[idx[0]].p.to_vec() - centroid) < 0.0 {
+            faces.push(EpaFace::new(&verts, [idx[0], idx[2], idx[1]])?);
+        } else {
+            faces.push(face);
+        }
+    }
+    let mut closest = faces[0];
+    for _ in 0..MAX_EPA_ITERATIONS {
+        closest = *faces
+            .iter()
+            .min_by(|a, b| a.dist.partial_cmp(&b.dist).unwrap_or(std::cmp::Ordering::Equal))?;
+        let w = diff.support(closest.n);
+        if closest.n.dot(w.p.to_vec()) - closest.dist <= EPA_TOLERANCE {
+            break;
+        }
+        let wi = verts.len();
+        verts.push(w);
+        // Remove every face that can see the new vertex and stitch the hole
+        // shut with new faces connecting its boundary to the vertex.
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        faces.retain(|face| {
+            if face.n.dot(w.p - verts[face.idx[0]].p) <= 0.0 {
+                return true;
+            }
+            for k in 0..3 {
+                let edge = (face.idx[k], face.idx[(k + 1) % 3]);
+                if let Some(pos) = horizon.iter().position(|&e| e == (edge.1, edge.0)) {
+                    horizon.swap_remove(pos);
+                } else {
+                    horizon.push(edge);
+                }
+            }
+            false
+        });
+        faces.extend(horizon.iter().filter_map(|&(i, j)| EpaFace::new(&verts, [i, j, wi])));
+        if faces.is_empty() {
+            break;
+        }
+    }
+    let [i, j, k] = closest.idx;
+    let tri = Triangle::from((verts[i].p, verts[j].p, verts[k].p));
+    let (v, w, u) = tri.barycentric(Point3::from_vec(closest.n * closest.dist));
+    let a = verts[i].a.to_vec() * u + verts[j].a.to_vec() * v + verts[k].a.to_vec() * w;
+    let b = verts[i].b.to_vec() * u + verts[j].b.to_vec() * v + verts[k].b.to_vec() * w;
+    Some(PenetrationDepth {
+        a: Point3::from_vec(a),
+        b: Point3::from_vec(b),
+        n: closest.n,
+        depth: closest.dist.max(0.0),
+    })
+}
+
+/// Runs EPA on the Minkowski difference of two shapes starting from a simplex
+/// of up to four vertices that encloses the origin. Simplices with fewer than
+/// four vertices are grown into a tetrahedron first.
+///
+/// Returns None if the simplex is empty or the polytope collapses.
+pub fn expand_polytope<S: Convex<SupportPoint>>(diff: &S, simplex: &[SupportPoint]) -> Option<PenetrationDepth> {
+    let (first, rest) = simplex.split_first()?;
+    let mut start = GjkSimplex::new(*first);
+    for &vert in rest.iter().take(3) {
+        if !start.has_vertex(&vert) {
+            start.push(vert);
+        }
+    }
+    epa(diff, start)
+}
+
+/// Finds how deeply two convex shapes overlap. Returns None if the shapes do
+/// not overlap.
+pub fn penetration_depth<A, B>(a: &A, b: &B) -> Option<PenetrationDepth>
+where
+    A: Convex,
+    B: Convex,
+{
+    let diff = MinkowskiDiff { s1: a, s2: b };
+    let simplex = gjk(&diff, Vector3::new(1.0, 0.0, 0.0));
+    if simplex.len < 4 && simplex.closest().magnitude2() > COLLISION_EPSILON {
+        return None;
+    }
+    epa(&diff, simplex)
+}
+
+/// An algorithm for finding the penetration of two overlapping shapes.
+///
+/// The solver is only chosen through `penetration_depth_with`. Discrete
+/// `Contacts` between volumes always use EPA.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PenetrationSolver {
+    /// The Expanding Polytope Algorithm, which finds the minimum penetration.
+    #[default]
+    Epa,
+    /// Minkowski Portal Refinement, which converges faster for smooth shapes
+    /// but may overestimate the penetration.
+    Mpr,
+}
+
+/// Finds how deeply two convex shapes overlap with the given solver. Returns
+/// None if the shapes do not overlap.
+pub fn penetration_depth_with<A, B>(a: &A, b: &B, solver: PenetrationSolver) -> Option<PenetrationDepth>
+where
+    A: Convex,
+    B: Convex,
+{
+    match solver {
+        PenetrationSolver::Epa => penetration_depth(a, b),
+        PenetrationSolver::Mpr => mpr_penetration(a, b),
+    }
+}
+
+/// Casts a ray against a convex shape using the GJK ray cast algorithm
+/// described by Gino van den Bergen. The ray starts at p and travels along d,
+/// stopping after max_t multiples of d.
+///
+/// Returns the time of impact in multiples of d along with the normal of the
+/// shape's surface at the point of impact. If p already lies inside of the
+/// shape the time of impact is zero and the normal is the zero vector.
+pub fn ray_cast<S: Convex>(
+    shape: &S,
+    p: Point3<f32>,
+    d: Vector3<f32>,
+    max_t: f32
+) -> Option<(f32, Vector3<f32>)> {
+    let mut t = 0.0;
+    let mut x = p;
+    let mut n = Vector3::zero();
+    let mut v = x - shape.support(d.normalize());
+    // The simplex is built from points of the form x - y, where y lies on the
+    // shape. Since x moves as the ray advances, only y is stored and the
+    // simplex is rebuilt from it at every step.
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let mut simplex = GjkSimplex::new(SupportPoint::from(origin));
+    simplex.len = 0;
+    for _ in 0..MAX_ITERATIONS {
+        let vv = v.magnitude2();
+        if simplex.len == 4 || vv <= COLLISION_EPSILON {
+            let n = if n.magnitude2() > 0.0 { n.normalize() } else { n };
+            return Some((t, n));
+        }
+        let y = shape.support(v / vv.sqrt());
+        let w = x - y;
+        let vw = v.dot(w);
+        if vw > 0.0 {
+            // v describes a plane separating the shape from x, so advance x
+            // along the ray until it meets that plane.
+            let vd = v.dot(d);
+            if vd >= 0.0 {
+                return None;
+            }
+            t -= vw / vd;
+            if t > max_t {
+                return None;
+            }
+            x = p + d * t;
+            n = v;
+        }
+        for vert in simplex.verts[..simplex.len].iter_mut() {
+            vert.p = Point3::from_vec(x - vert.b);
+        }
+        let new_vert = SupportPoint { p: Point3::from_vec(x - y), a: x, b: y };
+        if !simplex.has_vertex(&new_vert) {
+            simplex.push(new_vert);
+        }
+        v = simplex.solve();
+    }
+    // We failed to converge, but x is as close to the shape as we could get.
+    let n = if n.magnitude2() > 0.0 { n.normalize() } else { n };
+    Some((t, n))
+}
+
+/// A convex shape rotated by q about c and then translated by d.
+struct Swept<'a, S> {
+    shape: &'a S,
+    c: Point3<f32>,
+    q: Quaternion<f32>,
+    d: Vector3<f32>,
+}
+
+impl<'a, S: Convex> Convex for Swept<'a, S> {
+    fn support(&self, axis: Vector3<f32>) -> Point3<f32> {
+        let p = self.shape.support(self.q.conjugate().rotate_vector(axis));
+        self.c + self.q.rotate_vector(p - self.c) + self.d
+    }
+}
+
+/// Returns a bound on the distance of every point on the shape from the line
+/// through c along axis. Only the extent of the shape perpendicular to the
+/// axis is measured, as that is all that moves when spinning about it.
+fn bounding_radius<S: Convex>(shape: &S, c: Point3<f32>, axis: Vector3<f32>) -> f32 {
+    let u = if axis.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = axis.cross(u).normalize();
+    let w = axis.cross(u);
+    let extent = |d: Vector3<f32>| {
+        let max = d.dot(shape.support(d) - c);
+        let min = d.dot(shape.support(-d) - c);
+        max.abs().max(min.abs())
+    };
+    let (ru, rw) = (extent(u), extent(w));
+    (ru * ru + rw * rw).sqrt()
+}
+
+/// Finds the first time at which the second shape, moving along v, touches the
+/// first using conservative advancement. Returns a Contact with a time of
+/// impact in the interval [0, 1], in multiples of v, or None if the shapes do
+/// not touch.
+///
+/// If the shapes already overlap the contact is the deepest point of
+/// penetration with a time of zero.
+pub fn time_of_impact<A, B>(a: &A, b: &B, v: Vector3<f32>) -> Option<Contact>
+where
+    A: Convex,
+    B: Convex,
+{
+    conservative_advancement(a, b, Point3::origin(), 0.0, v, Vector3::zero())
+}
+
+/// Finds the first time at which the second shape, moving along v while
+/// rotating about c by the angular displacement omega, touches the first. The
+/// angular displacement is the axis of rotation scaled by the angle in radians
+/// turned over the interval.
+///
+/// Advancement is bounded by the fastest any point on the second shape can
+/// move towards the first, so the time of impact is never overshot. Shapes
+/// that spin quickly and narrowly miss may exhaust the iteration limit, in
+/// which case the contact at the last time reached is returned.
+pub fn rotational_time_of_impact<A, B>(
+    a: &A,
+    b: &B,
+    c: Point3<f32>,
+    v: Vector3<f32>,
+    omega: Vector3<f32>
+) -> Option<Contact>
+where
+    A: Convex,
+    B: Convex,
+{
+    let radius = if omega.is_zero() { 0.0 } else { bounding_radius(b, c, omega.normalize()) };
+    conservative_advancement(a, b, c, radius, v, omega)
+}
+
+fn conservative_advancement<A, B>(
+    a: &A,
+    b: &B,
+    c: Point3<f32>,
+    radius: f32,
+    v: Vector3<f32>,
+    omega: Vector3<f32>
+) -> Option<Contact>
+where
+    A: Convex,
+    B: Convex,
+{
+    let angle = omega.magnitude();
+    let axis = if angle > 0.0 { omega / angle } else { Vector3::unit_x() };
+    let mut t = 0.0;
+    let mut n = Vector3::zero();
+    let mut last = None;
+    for _ in 0..MAX_ITERATIONS {
+        let moved = Swept {
+            shape: b,
+            c,
+            q: Quaternion::from_axis_angle(axis, Rad(angle * t)),
+            d: v * t,
+        };
+        let pts = closest_points_from(a, &moved, n);
+        if !pts.axis.is_zero() {
+            n = pts.axis;
+        }
+        if pts.dist <= TOI_TOLERANCE {
+            if t == 0.0 {
+                if let Some(pen) = penetration_depth(a, b) {
+                    return Some(Contact { a: pen.a, b: pen.b, n: pen.n, t });
+                }
+            }
+            if n.is_zero() {
+                return None;
+            }
+            return Some(Contact { a: pts.a, b: pts.b, n, t });
+        }
+        // The shapes cannot touch before they have closed the distance
+        // between them along the separating axis, and no point on the second
+        // shape closes it faster than its linear velocity plus the speed of
+        // its furthest point from the axis of rotation. Only the part of the
+        // spin perpendicular to the separating axis moves points along it.
+        let closing = -v.dot(pts.axis) + pts.axis.cross(omega).magnitude() * radius;
+        if closing <= 0.0 {
+            return None;
+        }
+        last = Some(Contact { a: pts.a, b: pts.b, n, t });
+        t += pts.dist / closing;
+        if t > 1.0 {
+            return None;
+        }
+    }
+    // The shapes are still closing in, so report the last time that is known
+    // to be safe rather than letting them pass through each other.
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    mod gjk {
+        use cgmath::{One, Point3, Quaternion, Vector3, Zero};
+        use crate::geom::{AABB, OBB, Sphere};
+        use crate::gjk::*;
+
+        #[test]
+        fn test_closest_points() {
+            let aabb = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let sphere = Sphere{ c: Point3::new(3.0, 0.5, 0.0), r: 1.0 };
+            let pts = closest_points(&aabb, &sphere);
+            assert_relative_eq!(pts.a, Point3::new(1.0, 0.5, 0.0), epsilon = 0.001);
+            assert_relative_eq!(pts.b, Point3::new(2.0, 0.5, 0.0), epsilon = 0.001);
+
+            let p = Point3::new(0.5, -0.25, 0.0);
+            let pts = closest_points(&aabb, &p);
+            assert_relative_eq!(pts.a, p, epsilon = 0.001);
+            assert_relative_eq!(pts.b, p, epsilon = 0.001);
+        }
+
+        #[test]
+        fn test_closest_features() {
+            let a = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let s = Sphere{ c: Point3::new(0.0, 3.0, 0.0), r: 1.0 };
+            let pts = closest_points(&a, &s);
+            assert_relative_eq!(pts.dist, 1.0, epsilon = 0.001);
+            assert_relative_eq!(pts.axis, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+
+            // Warm starting from the previous axis gives the same answer.
+            let moved = Sphere{ c: Point3::new(0.1, 3.0, 0.0), r: 1.0 };
+            let warm = closest_points_from(&a, &moved, pts.axis);
+            assert_relative_eq!(warm.dist, 1.0, epsilon = 0.001);
+            assert_relative_eq!(warm.b, Point3::new(0.1, 2.0, 0.0), epsilon = 0.01);
+
+            let pts = closest_points(&a, &Point3::new(2.0, 2.0, 0.0));
+            assert_relative_eq!(pts.dist, 2.0f32.sqrt(), epsilon = 0.001);
+            assert_eq!(pts.features, (2, 1));
+
+            let pts = closest_points(&a, &s.c);
+            assert_eq!(pts.dist, 2.0);
+            let pts = closest_points(&a, &Point3::new(0.5, 0.0, 0.0));
+            assert_eq!(pts.dist, 0.0);
+            assert_eq!(pts.axis, Vector3::new(0.0, 0.0, 0.0));
+        }
+
+        #[test]
+        fn test_time_of_impact() {
+            let aabb = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let obb = OBB::new(
+                Point3::new(5.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0), Quaternion::one()
+            );
+            let contact = time_of_impact(&aabb, &obb, Vector3::new(-6.0, 0.0, 0.0)).unwrap();
+            assert_relative_eq!(contact.t, 0.5, epsilon = 0.001);
+            assert_relative_eq!(contact.n, Vector3::new(1.0, 0.0, 0.0), epsilon = 0.001);
+            assert_relative_eq!(contact.a.x, 1.0, epsilon = 0.001);
+            assert!(time_of_impact(&aabb, &obb, Vector3::new(-2.0, 0.0, 0.0)).is_none());
+            assert!(time_of_impact(&aabb, &obb, Vector3::new(6.0, 0.0, 0.0)).is_none());
+
+            let sphere = Sphere{ c: Point3::new(0.0, 0.5, 0.0), r: 1.0 };
+            let contact = time_of_impact(&aabb, &sphere, Vector3::new(0.0, 1.0, 0.0)).unwrap();
+            assert_eq!(contact.t, 0.0);
+            assert_relative_eq!(contact.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+        }
+
+        #[test]
+        fn test_rotational_time_of_impact() {
+            let wall = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(0.05, 4.0, 4.0) };
+            // A thin rod lying flat and spinning half a turn about its center
+            // sweeps through the wall without ever translating.
+            let rod = OBB::new(
+                Point3::new(-1.0, 0.0, 0.0), Vector3::new(0.05, 2.0, 0.05), Quaternion::one()
+            );
+            let spin = Vector3::new(0.0, 0.0, std::f32::consts::PI);
+            assert!(time_of_impact(&wall, &rod, Vector3::zero()).is_none());
+            let contact = rotational_time_of_impact(
+                &wall, &rod, rod.c, Vector3::zero(), spin
+            ).unwrap();
+            assert!(contact.t > 0.0 && contact.t < 0.5);
+            assert_relative_eq!(contact.n, Vector3::new(-1.0, 0.0, 0.0), epsilon = 0.01);
+            assert_relative_eq!(contact.a.x, -0.05, epsilon = 0.01);
+            // Spinning about its long axis never reaches the wall.
+            assert!(rotational_time_of_impact(
+                &wall, &rod, rod.c, Vector3::zero(), Vector3::new(0.0, 2.0, 0.0)
+            ).is_none());
+
+            // A rod spinning rapidly about its long axis while moving towards
+            // the wall. The spin only sweeps the rod's thin cross section, so it
+            // must not stall the advancement.
+            let rod = OBB::new(
+                Point3::new(-2.0, 0.0, 0.0), Vector3::new(0.2, 0.2, 2.0), Quaternion::one()
+            );
+            let v = Vector3::new(3.0, 0.0, 0.0);
+            let linear = time_of_impact(&wall, &rod, v).unwrap();
+            assert_relative_eq!(linear.t, 0.5833, epsilon = 0.001);
+            let contact = rotational_time_of_impact(
+                &wall, &rod, rod.c, v, Vector3::new(0.0, 0.0, 60.0)
+            ).unwrap();
+            assert!(contact.t > 0.5 && contact.t <= linear.t);
+            assert_relative_eq!(contact.n, Vector3::new(-1.0, 0.0, 0.0), epsilon = 0.01);
+        }
+
+        #[test]
+        fn test_penetration_depth() {
+            let a = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let b = AABB{ c: Point3::new(1.5, 0.2, 0.1), r: Vector3::new(1.0, 1.0, 1.0) };
+            let pen = penetration_depth(&a, &b).unwrap();
+            assert_relative_eq!(pen.depth, 0.5, epsilon = 0.001);
+            assert_relative_eq!(pen.n, Vector3::new(1.0, 0.0, 0.0), epsilon = 0.001);
+            assert_relative_eq!(pen.a - pen.b, pen.n * pen.depth, epsilon = 0.001);
+
+            let s = Sphere{ c: Point3::new(0.0, 0.7, 0.0), r: 0.5 };
+            let pen = penetration_depth(&a, &s).unwrap();
+            assert_relative_eq!(pen.depth, 0.8, epsilon = 0.01);
+            assert!(penetration_depth(&a, &Sphere{ c: Point3::new(0.0, 1.7, 0.0), r: 0.5 }).is_none());
+
+            // Deep overlaps where GJK ends on a degenerate simplex.
+            let pen = penetration_depth(&a, &Point3::new(0.0, 0.0, 0.0)).unwrap();
+            assert_relative_eq!(pen.depth, 1.0, epsilon = 0.001);
+        }
+
+        #[test]
+        fn test_ray_cast() {
+            let aabb = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let (t, n) = ray_cast(&aabb, Point3::new(-5.0, 0.5, 0.0), Vector3::new(2.0, 0.0, 0.0), 10.0).unwrap();
+            assert_relative_eq!(t, 2.0, epsilon = 0.001);
+            assert_relative_eq!(n, Vector3::new(-1.0, 0.0, 0.0), epsilon = 0.001);
+            assert!(ray_cast(&aabb, Point3::new(-5.0, 0.5, 0.0), Vector3::new(2.0, 0.0, 0.0), 1.5).is_none());
+            assert!(ray_cast(&aabb, Point3::new(-5.0, 0.5, 0.0), Vector3::new(-2.0, 0.0, 0.0), 10.0).is_none());
+            assert!(ray_cast(&aabb, Point3::new(-5.0, 1.5, 0.0), Vector3::new(2.0, 0.0, 0.0), 10.0).is_none());
+            let (t, _) = ray_cast(&aabb, Point3::new(0.5, 0.5, 0.0), Vector3::new(2.0, 0.0, 0.0), 10.0).unwrap();
+            assert_eq!(t, 0.0);
+        }
+    }
+}
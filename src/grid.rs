@@ -0,0 +1,254 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::ops::Index;
+
+use smallvec::SmallVec;
+
+use crate::bounds::BoundedBy;
+use crate::collision::Overlaps;
+use crate::geom::AABB;
+use crate::pool::Pool;
+
+/// Integer coordinates of a grid cell.
+type Cell = (i32, i32, i32);
+
+/// An object stored in a SpatialHash.
+struct GridEntry<V> {
+    bounds: AABB,
+    min: Cell,
+    max: Cell,
+    val: V,
+}
+
+/// A uniform grid of cubic cells, stored sparsely in a hash map.
+///
+/// Each entry is stored in every cell its bounds overlap, so the grid works
+/// best when entries are roughly the size of a cell or smaller. Unlike a BVH
+/// there is no hierarchy to maintain, which makes inserting, moving and
+/// removing large numbers of small objects cheap.
+pub struct SpatialHash<V> {
+    cell_size: f32,
+    cells: HashMap<Cell, SmallVec<[usize; 4]>>,
+    entries: Pool<GridEntry<V>>,
+}
+
+impl<V> SpatialHash<V> {
+    /// Creates an empty grid with cells of the given width.
+    pub fn new(cell_size: f32) -> Self {
+        if cell_size <= 0.0 {
+            panic!("cell size must be positive, got {}", cell_size);
+        }
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new(),
+            entries: Pool::new(),
+        }
+    }
+
+    /// Returns the width of each cell.
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Returns the number of entries in the grid.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determines if the grid is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.empty()
+    }
+
+    /// Returns the range of cells overlapped by bounds.
+    fn cell_range(&self, bounds: &AABB) -> (Cell, Cell) {
+        let cell = |x: f32| (x / self.cell_size).floor() as i32;
+        let lower = bounds.c - bounds.r;
+        let upper = bounds.c + bounds.r;
+        (
+            (cell(lower.x), cell(lower.y), cell(lower.z)),
+            (cell(upper.x), cell(upper.y), cell(upper.z)),
+        )
+    }
+
+    fn add_to_cells(&mut self, id: usize, min: Cell, max: Cell) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    self.cells.entry((x, y, z)).or_default().push(id);
+                }
+            }
+        }
+    }
+
+    fn remove_from_cells(&mut self, id: usize, min: Cell, max: Cell) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    let empty = if let Some(cell) = self.cells.get_mut(&(x, y, z)) {
+                        cell.retain(|other| *other != id);
+                        cell.is_empty()
+                    } else {
+                        false
+                    };
+                    if empty {
+                        self.cells.remove(&(x, y, z));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Inserts an item into the grid and returns its ID. The ID remains valid
+    /// until the item is removed.
+    pub fn insert<K: BoundedBy<AABB>>(&mut self, key: &K, val: V) -> usize {
+        let bounds = key.bounds();
+        let (min, max) = self.cell_range(&bounds);
+        let id = self.entries.push(GridEntry{ bounds, min, max, val });
+        self.add_to_cells(id, min, max);
+        id
+    }
+
+    /// Updates the bounds of an item. The item is only moved between cells if
+    /// the range of cells it overlaps has changed.
+    pub fn update<K: BoundedBy<AABB>>(&mut self, id: usize, key: &K) {
+        let bounds = key.bounds();
+        let (min, max) = self.cell_range(&bounds);
+        let (old_min, old_max) = (self.entries[id].min, self.entries[id].max);
+        self.entries[id].bounds = bounds;
+        if min != old_min || max != old_max {
+            self.remove_from_cells(id, old_min, old_max);
+            self.add_to_cells(id, min, max);
+            self.entries[id].min = min;
+            self.entries[id].max = max;
+        }
+    }
+
+    /// Removes an item from the grid.
+    pub fn remove(&mut self, id: usize) -> V {
+        let (min, max) = (self.entries[id].min, self.entries[id].max);
+        self.remove_from_cells(id, min, max);
+        self.entries.remove(id).val
+    }
+
+    /// Finds each entry in the grid whose bounds overlap the bounds of the
+    /// passed object. callback is called once for each overlapping entry.
+    pub fn query<K, F>(&self, key: &K, mut callback: F)
+    where
+        K: BoundedBy<AABB>,
+        F: FnMut(&V)
+    {
+        let bounds = key.bounds();
+        let (min, max) = self.cell_range(&bounds);
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    let cell = if let Some(cell) = self.cells.get(&(x, y, z)) {
+                        cell
+                    } else {
+                        continue;
+                    };
+                    for &id in cell.iter() {
+                        let entry = &self.entries[id];
+                        // An entry is found in every cell it shares with the
+                        // query, so only report it from the first one.
+                        if first_shared_cell(min, entry.min) == (x, y, z)
+                            && entry.bounds.overlaps(&bounds)
+                        {
+                            callback(&entry.val);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds each pair of distinct entries whose bounds overlap. callback is
+    /// called once for each overlapping pair.
+    pub fn pairs<F>(&self, mut callback: F)
+    where
+        F: FnMut(&V, &V)
+    {
+        for (&cell_coords, cell) in self.cells.iter() {
+            for (i, &a) in cell.iter().enumerate() {
+                let a = &self.entries[a];
+                for &b in cell[i + 1..].iter() {
+                    let b = &self.entries[b];
+                    if first_shared_cell(a.min, b.min) == cell_coords
+                        && a.bounds.overlaps(&b.bounds)
+                    {
+                        callback(&a.val, &b.val);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the lowest cell shared by two ranges of cells with the given lower
+/// corners, assuming the ranges overlap.
+fn first_shared_cell(a: Cell, b: Cell) -> Cell {
+    (cmp::max(a.0, b.0), cmp::max(a.1, b.1), cmp::max(a.2, b.2))
+}
+
+impl<V> Index<usize> for SpatialHash<V> {
+    type Output = V;
+
+    fn index(&self, id: usize) -> &V {
+        &self.entries[id].val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod grid {
+        use cgmath::Point3;
+        use crate::geom::Sphere;
+        use crate::grid::*;
+
+        #[test]
+        fn test_spatial_hash() {
+            let mut grid: SpatialHash<usize> = SpatialHash::new(1.0);
+            let spheres: Vec<Sphere> = (0..10)
+                .map(|i| Sphere{ c: Point3::new(i as f32 * 0.75, 0.5, 0.5), r: 0.4 })
+                .collect();
+            let ids: Vec<usize> = spheres.iter().enumerate().map(|(i, s)| grid.insert(s, i)).collect();
+
+            let mut pairs = Vec::new();
+            grid.pairs(|&a, &b| pairs.push((a.min(b), a.max(b))));
+            pairs.sort();
+            let expected: Vec<(usize, usize)> = (1..10).map(|i| (i - 1, i)).collect();
+            assert_eq!(pairs, expected);
+
+            let mut found = Vec::new();
+            grid.query(&Sphere{ c: Point3::new(3.0, 0.5, 0.5), r: 0.5 }, |&i| found.push(i));
+            found.sort();
+            assert_eq!(found, vec![3, 4, 5]);
+
+            grid.update(ids[4], &Sphere{ c: Point3::new(-10.0, 0.5, 0.5), r: 0.4 });
+            let mut found = Vec::new();
+            grid.query(&Sphere{ c: Point3::new(3.0, 0.5, 0.5), r: 0.5 }, |&i| found.push(i));
+            found.sort();
+            assert_eq!(found, vec![3, 5]);
+
+            assert_eq!(grid.remove(ids[3]), 3);
+            grid.query(&spheres[3], |&i| assert_ne!(i, 3));
+            assert_eq!(grid.len(), 9);
+        }
+    }
+}
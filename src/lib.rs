@@ -120,6 +120,9 @@ pub use crate::bvh::*;
 mod bounds;
 pub use crate::bounds::*;
 
+mod broadphase;
+pub use crate::broadphase::*;
+
 pub mod bitset;
 
 mod compound;
@@ -131,20 +134,35 @@ pub use crate::collision::*;
 mod solver;
 pub use crate::solver::*;
 
+mod frustum;
+pub use crate::frustum::*;
+
 mod geom;
 pub use crate::geom::*;
 
+mod gjk;
+pub use crate::gjk::*;
+
+mod grid;
+pub use crate::grid::*;
+
 mod manifold;
 pub use crate::manifold::*;
 
 mod mesh;
 pub use crate::mesh::*;
 
+mod mpr;
+pub use crate::mpr::*;
+
 mod physics;
 pub use crate::physics::*;
 
 mod pool;
 pub use crate::pool::*;
 
+mod sap;
+pub use crate::sap::*;
+
 mod simplex;
 pub use crate::simplex::*;
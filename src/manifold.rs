@@ -13,6 +13,7 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with MGF. If not, see <http://www.gnu.org/licenses/>.
 
+use std::cmp;
 use std::f32;
 use std::marker::PhantomData;
 
@@ -99,6 +100,63 @@ impl<Params: PruningParams> ContactPruner<Params> {
             }
         }
         self.contacts.push(new_contact);
+        if self.contacts.len() > 4 {
+            self.reduce();
+        }
+    }
+
+    /// Returns the number of contacts currently kept.
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    /// Returns true if no contacts are kept.
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    /// Reduces the contacts to four: the deepest contact, and the three
+    /// that together with it enclose the largest area on the contact plane.
+    fn reduce(&mut self) {
+        let deepest = deepest_contact(&self.contacts);
+        let n = self.contacts[deepest].global.n;
+        let p = |i: usize| self.contacts[i].global.a;
+        let area = |i: usize, j: usize, k: usize| (p(j) - p(i)).cross(p(k) - p(i)).dot(n);
+        let others = || (0..self.contacts.len()).filter(|&i| i != deepest);
+        // The point furthest from the deepest point.
+        let second = others()
+            .max_by(|&i, &j| {
+                let di = (p(i) - p(deepest)).magnitude2();
+                let dj = (p(j) - p(deepest)).magnitude2();
+                cmp_measure(di, dj)
+            })
+            .unwrap();
+        // The point forming the largest triangle with the first two.
+        let third = others()
+            .filter(|&i| i != second)
+            .max_by(|&i, &j| {
+                cmp_measure(area(deepest, second, i).abs(), area(deepest, second, j).abs())
+            })
+            .unwrap();
+        // The point that adds the most area outside of the triangle. The
+        // area added beyond an edge is positive only if the point lies on the
+        // opposite side of the edge from the triangle.
+        let tri = [deepest, second, third];
+        let winding = area(deepest, second, third).signum();
+        let added = |i: usize| {
+            (0..3)
+                .map(|e| -area(tri[e], tri[(e + 1) % 3], i) * winding)
+                .fold(0.0, f32::max)
+        };
+        let fourth = others()
+            .filter(|&i| i != second && i != third)
+            .max_by(|&i, &j| cmp_measure(added(i), added(j)))
+            .unwrap();
+        let kept: SmallVec<[LocalContact; 4]> = [deepest, second, third, fourth]
+            .iter()
+            .map(|&i| self.contacts[i])
+            .collect();
+        self.contacts = kept;
     }
 
     pub fn clear(&mut self) {
@@ -128,20 +186,42 @@ impl From<LocalContact> for Manifold {
     }
 }
 
+/// Compares two distances or areas, treating NaN as the smallest.
+fn cmp_measure(a: f32, b: f32) -> cmp::Ordering {
+    let key = |x: f32| if x.is_nan() { f32::NEG_INFINITY } else { x };
+    key(a).partial_cmp(&key(b)).unwrap_or(cmp::Ordering::Equal)
+}
+
+/// Returns the index of the contact with the greatest penetration along its
+/// normal. Continuous contacts have no penetration, so the first is chosen.
+fn deepest_contact(contacts: &[LocalContact]) -> usize {
+    let depth = |lc: &LocalContact| (lc.global.a - lc.global.b).dot(lc.global.n);
+    let mut deepest = 0;
+    for (i, lc) in contacts.iter().enumerate() {
+        if depth(lc) > depth(&contacts[deepest]) {
+            deepest = i;
+        }
+    }
+    deepest
+}
+
 impl<P: PruningParams> From<ContactPruner<P>> for Manifold {
+    /// The normal of the manifold is the normal of the deepest contact, which
+    /// unlike an average cannot cancel out or point into either object.
     fn from(pruner: ContactPruner<P>) -> Self {
-        let mut contacts: SmallVec<[(Point3<f32>, Point3<f32>); 4]>
-            = SmallVec::with_capacity(pruner.contacts.len());
-        let avg_normal = pruner.contacts.iter()
-            .fold(Vector3::zero(),
-                  |sum, lc| {
-                      contacts.push((lc.local_a, lc.local_b));
-                      sum + lc.global.n
-                  }) / (pruner.contacts.len() as f32);
+        let contacts: SmallVec<[(Point3<f32>, Point3<f32>); 4]> = pruner.contacts
+            .iter()
+            .map(|lc| (lc.local_a, lc.local_b))
+            .collect();
+        let normal = if pruner.contacts.is_empty() {
+            Vector3::zero()
+        } else {
+            pruner.contacts[deepest_contact(&pruner.contacts)].global.n
+        };
         Manifold {
             time: pruner.min_col_time,
-            normal: avg_normal,
-            tangent_vector: compute_basis(&avg_normal),
+            normal,
+            tangent_vector: compute_basis(&normal),
             contacts
         }
     }
@@ -163,3 +243,68 @@ impl Manifold {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    mod manifold {
+        use cgmath::{Point3, Vector3};
+        use crate::collision::{Contact, LocalContact};
+        use crate::manifold::*;
+
+        #[test]
+        fn test_reduction() {
+            let mut pruner: ContactPruner = ContactPruner::new();
+            // A grid of contacts on the floor, the deepest of which is at the
+            // center of the grid.
+            for i in 0..5 {
+                for j in 0..5 {
+                    let a = Point3::new(i as f32 * 2.0, 0.0, j as f32 * 2.0);
+                    let depth = if i == 2 && j == 2 { 0.1 } else { 0.01 };
+                    pruner.push(LocalContact {
+                        local_a: a,
+                        local_b: a,
+                        global: Contact {
+                            a,
+                            b: a + Vector3::new(0.0, -depth, 0.0),
+                            n: Vector3::new(0.0, 1.0, 0.0),
+                            t: 0.0,
+                        },
+                    });
+                    assert!(pruner.len() <= 4);
+                }
+            }
+            let manifold = Manifold::from(pruner);
+            assert_eq!(manifold.len(), 4);
+            assert_eq!(manifold.normal, Vector3::new(0.0, 1.0, 0.0));
+            // The deepest point is kept along with three corners of the grid,
+            // which enclose the most area of any points that could be chosen.
+            let points: Vec<_> = manifold.contacts.iter().map(|c| (c.0.x as i32, c.0.z as i32)).collect();
+            assert!(points.contains(&(4, 4)));
+            let corners = [(0, 0), (0, 8), (8, 0), (8, 8)];
+            assert_eq!(points.iter().filter(|p| corners.contains(p)).count(), 3);
+        }
+
+        #[test]
+        fn test_reduction_nan() {
+            let mut pruner: ContactPruner = ContactPruner::new();
+            let points = [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(4.0, 0.0, 0.0),
+                Point3::new(4.0, 0.0, 4.0),
+                Point3::new(0.0, 0.0, 4.0),
+                Point3::new(f32::NAN, 0.0, 2.0),
+            ];
+            for &a in points.iter() {
+                pruner.push(LocalContact {
+                    local_a: a,
+                    local_b: a,
+                    global: Contact { a, b: a, n: Vector3::new(0.0, 1.0, 0.0), t: 0.0 },
+                });
+            }
+            // The contact with a NaN position is the one dropped.
+            let manifold = Manifold::from(pruner);
+            assert_eq!(manifold.len(), 4);
+            assert!(manifold.contacts.iter().all(|c| !c.0.x.is_nan()));
+        }
+    }
+}
@@ -13,18 +13,51 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with MGF. If not, see <http://www.gnu.org/licenses/>.
 
+use std::f32;
 use std::ops::{AddAssign, SubAssign};
 use std::vec::Vec;
 
 use crate::bvh::*;
 use crate::geom::*;
 use crate::collision::*;
+use crate::gjk::{closest_points, ray_cast};
 use crate::bounds::{BoundedBy};
 use cgmath::prelude::*;
 use cgmath::{Vector3, Point3, Zero, Rotation3};
 
 use serde::{Serialize, Deserialize};
 
+/// An edge of a mesh face along with the face on the other side of it.
+///
+/// The edges of a face (a, b, c) are ordered (a, b), (b, c) and (c, a).
+#[derive(Copy, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct FaceEdge {
+    /// The face sharing this edge, if any.
+    pub neighbor: Option<usize>,
+    /// True if the surface folds away from the face across this edge, as it
+    /// does on the outside of a box. Flat and concave edges can only be
+    /// touched along the normals of the faces they join.
+    pub convex: bool,
+}
+
+/// Collision flags of a mesh face.
+///
+/// By default a face is one sided: objects that reach it from behind are
+/// pushed back out through the front, as described in the README.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+pub struct FaceFlags {
+    /// Objects behind the face are pushed out the back of the face instead,
+    /// as if it were backed by a copy with reversed winding.
+    pub double_sided: bool,
+    /// Objects behind the face pass through it. Rays only hit the front of
+    /// the face.
+    pub one_way: bool,
+    /// The face never collides with anything.
+    pub no_collide: bool,
+}
+
 /// A triangle mesh is a set of triangles that forms some sort of mesh. There
 /// are no requirements on the convexivity of the mesh.
 #[derive(Clone)]
@@ -33,6 +66,12 @@ pub struct Mesh {
     pub x: Vector3<f32>,
     pub verts: Vec<Point3<f32>>,
     pub faces: Vec<(usize, usize, usize)>,
+    /// The edges of each face, used to correct contacts on internal edges.
+    #[serde(default)]
+    pub edges: Vec<[FaceEdge; 3]>,
+    /// The collision flags of each face.
+    #[serde(default)]
+    pub flags: Vec<FaceFlags>,
     pub bvh: BVH<AABB, usize>, 
 }
 
@@ -42,6 +81,8 @@ impl Mesh {
             x: Vector3::zero(),
             verts: Vec::new(),
             faces: Vec::new(),
+            edges: Vec::new(),
+            flags: Vec::new(),
             bvh: BVH::new(),
         }
     }
@@ -51,6 +92,8 @@ impl Mesh {
             x: Vector3::zero(),
             verts: Vec::with_capacity(cap_verts),
             faces: Vec::with_capacity(cap_faces),
+            edges: Vec::with_capacity(cap_faces),
+            flags: Vec::with_capacity(cap_faces),
             bvh: BVH::with_capacity(cap_faces),
         }
     }
@@ -68,9 +111,288 @@ impl Mesh {
         let tri = Triangle::from((a, b, c));
         let index = self.faces.len();
         self.faces.push(f);
+        self.edges.push(Default::default());
+        self.flags.push(Default::default());
         self.bvh.insert(&tri, index);
+        self.link_face(index);
         index
     }
+
+    /// Adds a batch of faces to the mesh and rebuilds the BVH from scratch.
+    /// This takes about as long as calling push_face for each face, but
+    /// produces a tree that is considerably cheaper to query. Returns the index
+    /// of the first face added.
+    pub fn push_faces<I>(&mut self, faces: I) -> usize
+    where
+        I: IntoIterator<Item = (usize, usize, usize)>
+    {
+        let index = self.faces.len();
+        self.faces.extend(faces);
+        self.rebuild_bvh();
+        self.edges = vec![Default::default(); self.faces.len()];
+        self.flags.resize(self.faces.len(), Default::default());
+        for i in 0..self.faces.len() {
+            self.link_face(i);
+        }
+        index
+    }
+
+    /// Returns the collision flags of the ith face.
+    pub fn face_flags(&self, i: usize) -> FaceFlags {
+        self.flags.get(i).cloned().unwrap_or_default()
+    }
+
+    /// Sets the collision flags of the ith face.
+    pub fn set_face_flags(&mut self, i: usize, flags: FaceFlags) {
+        if self.flags.len() < self.faces.len() {
+            self.flags.resize(self.faces.len(), Default::default());
+        }
+        self.flags[i] = flags;
+    }
+
+    /// Returns the ith face as it should be tested for collision with an
+    /// object positioned at p, or None if the face should not collide with
+    /// the object. The back of a double sided face is returned as a copy of
+    /// the face with reversed winding.
+    pub fn collision_face(&self, i: usize, p: Point3<f32>) -> Option<Triangle> {
+        let flags = self.face_flags(i);
+        if flags.no_collide {
+            return None;
+        }
+        let tri = self.face(i);
+        if tri.normal().dot(p.to_vec() - tri.a) >= 0.0 {
+            return Some(tri);
+        }
+        if flags.one_way {
+            None
+        } else if flags.double_sided {
+            Some(Triangle{ a: tri.a, b: tri.c, c: tri.b })
+        } else {
+            Some(tri)
+        }
+    }
+
+    /// Returns the vertex indices of the kth edge of the ith face.
+    fn edge_verts(&self, i: usize, k: usize) -> (usize, usize) {
+        let (a, b, c) = self.faces[i];
+        [(a, b), (b, c), (c, a)][k]
+    }
+
+    /// Finds the faces sharing an edge with the ith face and records the
+    /// adjacency on both faces.
+    fn link_face(&mut self, i: usize) {
+        let (a, b, c) = self.faces[i];
+        let tri = Triangle::from((self.verts[a], self.verts[b], self.verts[c]));
+        let bounds: AABB = tri.bounds();
+        let mut candidates = Vec::new();
+        self.bvh.query(&bounds, |&j| if j != i { candidates.push(j) });
+        for j in candidates {
+            for k in 0..3 {
+                let (p, q) = self.edge_verts(i, k);
+                for l in 0..3 {
+                    if self.edge_verts(j, l) == (q, p) || self.edge_verts(j, l) == (p, q) {
+                        self.edges[i][k] = FaceEdge{ neighbor: Some(j), convex: self.folds_away(i, j) };
+                        self.edges[j][l] = FaceEdge{ neighbor: Some(i), convex: self.folds_away(j, i) };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if the ith face's neighbor j lies behind the ith face.
+    fn folds_away(&self, i: usize, j: usize) -> bool {
+        const CONVEX_TOLERANCE: f32 = 0.001;
+        let (a, b, c) = self.faces[i];
+        let tri = Triangle::from((self.verts[a], self.verts[b], self.verts[c]));
+        let (d, e, f) = self.faces[j];
+        let opposite = [d, e, f].iter().cloned().find(|&v| v != a && v != b && v != c);
+        match opposite {
+            Some(v) => {
+                let to = self.verts[v].to_vec() - tri.a;
+                tri.normal().dot(to) < -CONVEX_TOLERANCE * to.magnitude()
+            },
+            None => false,
+        }
+    }
+
+    /// Corrects the normal n of a contact at p, a point on the ith face, for
+    /// contacts on internal edges and vertices. An object sliding across a
+    /// flat or concave edge can only be touching the faces it joins, so the
+    /// normal is replaced with the face normal. On a convex edge the normal
+    /// is only kept if it lies between the normals of the two faces.
+    ///
+    /// Contacts with the back of a face are returned unchanged.
+    pub fn correct_normal(&self, i: usize, p: Point3<f32>, n: Vector3<f32>) -> Vector3<f32> {
+        const BARY_TOLERANCE: f32 = 0.0001;
+        let edges = match self.edges.get(i) {
+            Some(edges) => edges,
+            None => return n,
+        };
+        let tri = self.face(i);
+        let face_n = tri.normal();
+        if n.dot(face_n) <= 0.0 {
+            return n;
+        }
+        let (wb, wc, wa) = tri.barycentric(p);
+        let corner = |e1: usize, e2: usize| {
+            if [edges[e1], edges[e2]].iter().all(|e| e.neighbor.is_some() && !e.convex) {
+                face_n
+            } else {
+                n
+            }
+        };
+        if wa >= 1.0 - BARY_TOLERANCE {
+            return corner(0, 2);
+        } else if wb >= 1.0 - BARY_TOLERANCE {
+            return corner(0, 1);
+        } else if wc >= 1.0 - BARY_TOLERANCE {
+            return corner(1, 2);
+        }
+        let k = if wc <= BARY_TOLERANCE {
+            0
+        } else if wa <= BARY_TOLERANCE {
+            1
+        } else if wb <= BARY_TOLERANCE {
+            2
+        } else {
+            return n;
+        };
+        let edge = edges[k];
+        let j = match edge.neighbor {
+            Some(j) => j,
+            None => return n,
+        };
+        if !edge.convex {
+            return face_n;
+        }
+        // Measure the angle of each normal around the edge, starting from the
+        // face normal. A valid normal lies between the two face normals.
+        let (p0, p1) = [(tri.a, tri.b), (tri.b, tri.c), (tri.c, tri.a)][k];
+        let axis = (p1 - p0).normalize();
+        let angle = |v: Vector3<f32>| {
+            let v = v - axis * axis.dot(v);
+            face_n.cross(v).dot(axis).atan2(face_n.dot(v))
+        };
+        let limit = angle(self.face(j).normal());
+        let theta = angle(n);
+        if theta * limit >= 0.0 && theta.abs() <= limit.abs() + BARY_TOLERANCE {
+            n
+        } else {
+            face_n
+        }
+    }
+
+    /// Rebuilds the BVH from the current vertices and faces.
+    fn rebuild_bvh(&mut self) {
+        let verts = &self.verts;
+        self.bvh = BVH::build(
+            self.faces
+                .iter()
+                .enumerate()
+                .map(|(i, &(a, b, c))| (Triangle::from((verts[a], verts[b], verts[c])), i))
+        );
+    }
+
+    /// Returns the ith face of the mesh as a Triangle in world space.
+    pub fn face(&self, i: usize) -> Triangle {
+        let (a, b, c) = self.faces[i];
+        Triangle::from((self.verts[a] + self.x, self.verts[b] + self.x, self.verts[c] + self.x))
+    }
+
+    /// Finds the face closest to p. Returns the index of the face along with
+    /// the closest point on it, or None if the mesh has no faces.
+    pub fn closest_face(&self, p: Point3<f32>) -> Option<(usize, Point3<f32>)> {
+        self.bvh
+            .nearest(p + -self.x, |&i| (self.face(i).closest_point(p) - p).magnitude())
+            .map(|(&i, _)| (i, self.face(i).closest_point(p)))
+    }
+
+    /// Finds the first face hit by a particle. Returns the index of the face
+    /// along with the intersection. Faces flagged no_collide are ignored and
+    /// one_way faces can only be hit from the front.
+    pub fn face_intersection<P: Particle>(&self, p: &P) -> Option<(usize, Intersection)> {
+        let r = Ray{ p: p.pos() + -self.x, d: p.dir() };
+        self.bvh
+            .raycast_closest(&r, |&face_index| {
+                let flags = self.face_flags(face_index);
+                let tri = self.face(face_index);
+                if flags.no_collide || (flags.one_way && tri.normal().dot(p.dir()) >= 0.0) {
+                    return None;
+                }
+                p.intersection(&tri)
+            })
+            .map(|(&face_index, inter)| (face_index, inter))
+    }
+
+    /// Returns the distance from p to the surface of the mesh. The distance is
+    /// negative if p lies inside of the mesh.
+    ///
+    /// Inside and outside are determined with the angle-weighted pseudo-normal
+    /// of the closest feature, so the result is only meaningful for closed
+    /// meshes whose face normals all point outward. Returns infinity if the
+    /// mesh has no faces.
+    pub fn signed_distance(&self, p: Point3<f32>) -> f32 {
+        let (i, q) = if let Some(closest) = self.closest_face(p) {
+            closest
+        } else {
+            return f32::INFINITY;
+        };
+        let d = p - q;
+        let dist = d.magnitude();
+        if self.pseudo_normal(i, q).dot(d) < 0.0 {
+            -dist
+        } else {
+            dist
+        }
+    }
+
+    /// Computes the angle-weighted pseudo-normal of the surface at q, a point
+    /// on the ith face. The feature of the face that q lies on is found, and
+    /// every face sharing its vertices is weighted by the angle it spans
+    /// around q: a full turn in its interior, half of one on an edge and the
+    /// angle of its corner at a vertex.
+    fn pseudo_normal(&self, i: usize, q: Point3<f32>) -> Vector3<f32> {
+        const BARY_TOLERANCE: f32 = 0.0001;
+        let tri = self.face(i);
+        let (wb, wc, wa) = tri.barycentric(q);
+        let (a, b, c) = self.faces[i];
+        let feature: Vec<usize> = [(a, wa), (b, wb), (c, wc)]
+            .iter()
+            .filter(|&&(_, w)| w > BARY_TOLERANCE)
+            .map(|&(v, _)| v)
+            .collect();
+        if feature.len() == 3 {
+            return tri.normal();
+        }
+        // Every face sharing the feature overlaps the bounds of its vertices.
+        let mut min = self.verts[feature[0]];
+        let mut max = min;
+        for &v in feature[1..].iter() {
+            for k in 0..3 {
+                min[k] = min[k].min(self.verts[v][k]);
+                max[k] = max[k].max(self.verts[v][k]);
+            }
+        }
+        let margin = Vector3::new(COLLISION_EPSILON, COLLISION_EPSILON, COLLISION_EPSILON);
+        let probe = AABB{ c: min.midpoint(max), r: (max - min) * 0.5 + margin };
+        let mut n = Vector3::zero();
+        self.bvh.query(&probe, |&j| {
+            let (d, e, f) = self.faces[j];
+            if !feature.iter().all(|v| *v == d || *v == e || *v == f) {
+                return;
+            }
+            let angle = if feature.len() == 2 {
+                f32::consts::PI
+            } else {
+                // The angle of the corner of the face at the vertex.
+                let v = feature[0];
+                let (p, q) = if v == d { (e, f) } else if v == e { (f, d) } else { (d, e) };
+                (self.verts[p] - self.verts[v]).angle(self.verts[q] - self.verts[v]).0
+            };
+            n += self.face(j).normal() * angle;
+        });
+        n
+    }
 }
 
 impl AddAssign<Vector3<f32>> for Mesh {
@@ -90,8 +412,24 @@ impl Shape for Mesh {
         Point3::from_vec(self.x)
     }
 
-    fn closest_point(&self, _to: Point3<f32>) -> Point3<f32> {
-        unimplemented!();
+    /// Returns the closest point on the surface of the mesh. Returns the center
+    /// of the mesh if it has no faces.
+    fn closest_point(&self, to: Point3<f32>) -> Point3<f32> {
+        self.closest_face(to).map_or(self.center(), |(_, q)| q)
+    }
+}
+
+impl Contains<Point3<f32>> for Mesh {
+    /// Determines if a point lies inside of a closed mesh. See
+    /// `Mesh::signed_distance` for the requirements placed on the mesh.
+    fn contains(&self, p: &Point3<f32>) -> bool {
+        self.signed_distance(*p) <= 0.0
+    }
+}
+
+impl<P: Particle> Intersects<Mesh> for P {
+    fn intersection(&self, rhs: &Mesh) -> Option<Intersection> {
+        rhs.face_intersection(self).map(|(_, inter)| inter)
     }
 }
 
@@ -101,36 +439,30 @@ impl Volumetric for Mesh {
         for vert in self.verts.iter_mut() {
             *vert = rot.rotate_point(*vert);
         }
-        self.bvh.clear();
-        for (i, &(a, b, c)) in self.faces.iter().enumerate() {
-            let tri = Triangle::from(
-                (self.verts[a], self.verts[b], self.verts[c])
-            );
-            self.bvh.insert(&tri, i);
-        }
+        self.rebuild_bvh();
         self
     }
 }
 
 impl<RHS> Contacts<RHS> for Mesh
 where
-    RHS: Contacts<Triangle> + Contacts<Rectangle> + BoundedBy<AABB>
+    RHS: Contacts<Triangle> + BoundedBy<AABB> + Positioned
 {
     fn contacts<F: FnMut(Contact)>(&self, rhs: &RHS, mut callback: F) -> bool {
         let mut collided = false;
+        let p = rhs.position();
         self.bvh.query(&(rhs.bounds() - self.x), |&face_index| {
-            let (a, b, c) = self.faces[face_index];
-            let a = self.verts[a] + self.x;
-            let b = self.verts[b] + self.x;
-            let c = self.verts[c] + self.x;
-            let tri = Triangle::from((a, b, c));
+            let tri = match self.collision_face(face_index, p) {
+                Some(tri) => tri,
+                None => return,
+            };
             rhs.contacts(&tri, |c| {
                 collided = true;
                 callback(Contact {
                     a: c.b,
                     b: c.a,
                     t: c.t,
-                    n: -c.n,
+                    n: self.correct_normal(face_index, c.b, -c.n),
                 });
             });
         });
@@ -165,12 +497,8 @@ impl ConvexMesh {
     }
 
     pub fn push(&mut self, p: Point3<f32>) {
-        let prev_center = self.sum / self.verts.len() as f32;
         self.sum += p.to_vec();
         self.verts.push(p);
-        let new_center = self.sum / self.verts.len() as f32;
-        let disp = new_center - prev_center;
-        self.x += disp;
     }
 }
 
@@ -205,8 +533,13 @@ impl Shape for ConvexMesh {
         Point3::from_vec(self.x + self.sum / self.verts.len() as f32)
     }
 
-    fn closest_point(&self, _to: Point3<f32>) -> Point3<f32> {
-        unimplemented!();
+    /// Returns the closest point on the hull. Returns the offset of the hull
+    /// if it has no vertices.
+    fn closest_point(&self, to: Point3<f32>) -> Point3<f32> {
+        if self.verts.is_empty() {
+            return Point3::from_vec(self.x);
+        }
+        closest_points(self, &to).a
     }
 }
 
@@ -220,6 +553,27 @@ impl Volumetric for ConvexMesh {
     }
 }
 
+impl BoundedBy<AABB> for ConvexMesh {
+    fn bounds(&self) -> AABB {
+        let mut min = Vector3::zero();
+        let mut max = Vector3::zero();
+        for i in 0..3 {
+            let mut axis = Vector3::zero();
+            axis[i] = 1.0;
+            max[i] = self.support(axis)[i];
+            min[i] = self.support(-axis)[i];
+        }
+        AABB{ c: Point3::from_vec((max + min) * 0.5), r: (max - min) * 0.5 }
+    }
+}
+
+impl<P: Particle> Intersects<ConvexMesh> for P {
+    fn intersection(&self, rhs: &ConvexMesh) -> Option<Intersection> {
+        ray_cast(rhs, self.pos(), self.dir(), P::DT)
+            .map(|(t, _)| Intersection{ p: self.pos() + self.dir() * t, t })
+    }
+}
+
 impl Convex for ConvexMesh {
     fn support(&self, d: Vector3<f32>) -> Point3<f32> {
         let mut best_vert = self.verts[0];
@@ -231,6 +585,262 @@ impl Convex for ConvexMesh {
                 best_norm = norm;
             }
         }
-        best_vert
+        best_vert + self.x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod mesh {
+        use cgmath::{InnerSpace, One, Point3, Quaternion, Vector3};
+        use crate::collision::*;
+        use crate::geom::*;
+        use crate::mesh::*;
+
+        /// Builds a closed unit cube with outward facing normals.
+        fn unit_cube() -> Mesh {
+            cuboid(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))
+        }
+
+        /// Builds a closed box spanning lo to hi with outward facing normals.
+        fn cuboid(lo: Point3<f32>, hi: Point3<f32>) -> Mesh {
+            let mut mesh = Mesh::new();
+            for i in 0..8 {
+                let corner = |bit: usize, k: usize| if i & bit == 0 { lo[k] } else { hi[k] };
+                mesh.push_vert(Point3::new(corner(1, 0), corner(2, 1), corner(4, 2)));
+            }
+            let quads = [
+                [0, 4, 6, 2], [1, 3, 7, 5],
+                [0, 1, 5, 4], [2, 6, 7, 3],
+                [0, 2, 3, 1], [4, 5, 7, 6],
+            ];
+            mesh.push_faces(quads.iter().flat_map(|q| vec![(q[0], q[1], q[2]), (q[0], q[2], q[3])]));
+            mesh
+        }
+
+        #[test]
+        fn test_closest_point() {
+            let mut mesh = unit_cube();
+            mesh.x = Vector3::new(1.0, 0.0, 0.0);
+            assert_relative_eq!(mesh.closest_point(Point3::new(4.0, 0.5, 0.25)),
+                                Point3::new(2.0, 0.5, 0.25), epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(mesh.closest_point(Point3::new(1.5, 0.4, 0.5)),
+                                Point3::new(1.5, 0.0, 0.5), epsilon = COLLISION_EPSILON);
+            assert_eq!(Mesh::new().closest_point(Point3::new(1.0, 2.0, 3.0)), Point3::new(0.0, 0.0, 0.0));
+
+            let hull = ConvexMesh::from(mesh.verts.clone());
+            assert_relative_eq!(hull.closest_point(Point3::new(3.0, 0.5, 0.25)),
+                                Point3::new(1.0, 0.5, 0.25), epsilon = 0.0001);
+            assert_relative_eq!(hull.closest_point(Point3::new(0.5, 0.5, 0.25)),
+                                Point3::new(0.5, 0.5, 0.25), epsilon = 0.0001);
+            let mut moved = hull.clone();
+            moved += Vector3::new(10.0, 0.0, 0.0);
+            assert_relative_eq!(moved.closest_point(Point3::new(10.0, 0.0, 0.0)),
+                                Point3::new(10.0, 0.0, 0.0), epsilon = 0.0001);
+            assert_relative_eq!(moved.closest_point(Point3::new(13.0, 0.5, 0.25)),
+                                Point3::new(11.0, 0.5, 0.25), epsilon = 0.0001);
+            assert_eq!(ConvexMesh::new().closest_point(Point3::new(1.0, 2.0, 3.0)), Point3::new(0.0, 0.0, 0.0));
+        }
+
+        #[test]
+        fn test_signed_distance() {
+            let mesh = unit_cube();
+            assert_relative_eq!(mesh.signed_distance(Point3::new(0.5, 0.5, 0.5)), -0.5, epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(mesh.signed_distance(Point3::new(0.5, 0.5, 3.0)), 2.0, epsilon = COLLISION_EPSILON);
+            // Closest features are an edge and a vertex shared by several faces.
+            assert_relative_eq!(mesh.signed_distance(Point3::new(2.0, 2.0, 0.5)), 2.0f32.sqrt(), epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(mesh.signed_distance(Point3::new(-1.0, -1.0, -1.0)), 3.0f32.sqrt(), epsilon = COLLISION_EPSILON);
+            assert!(mesh.signed_distance(Point3::new(0.9, 0.9, 0.1)) < 0.0);
+            assert!(mesh.contains(&Point3::new(0.1, 0.2, 0.3)));
+            assert!(!mesh.contains(&Point3::new(1.1, 0.2, 0.3)));
+
+            // Only the faces touching the closest feature decide the sign, no
+            // matter how far the mesh is from the origin.
+            let slab = cuboid(Point3::new(199.95, -1.0, -1.0), Point3::new(200.05, 1.0, 1.0));
+            assert_relative_eq!(slab.signed_distance(Point3::new(200.02, 0.3, 0.4)), -0.03, epsilon = 0.0001);
+            assert!(slab.contains(&Point3::new(200.02, 0.3, 0.4)));
+            assert!(!slab.contains(&Point3::new(200.08, 0.3, 0.4)));
+        }
+
+        #[test]
+        fn test_mesh_intersection() {
+            let mut mesh = unit_cube();
+            mesh.x = Vector3::new(1.0, 0.0, 0.0);
+            let ray = Ray{ p: Point3::new(1.5, 0.5, 5.0), d: Vector3::new(0.0, 0.0, -1.0) };
+            let (face, inter) = mesh.face_intersection(&ray).unwrap();
+            assert_relative_eq!(inter.t, 4.0, epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(mesh.face(face).normal(), Vector3::new(0.0, 0.0, 1.0), epsilon = COLLISION_EPSILON);
+            let seg = Segment::from((Point3::new(1.5, 0.5, 5.0), Point3::new(1.5, 0.5, 2.0)));
+            assert!(seg.intersection(&mesh).is_none());
+            let seg = Segment::from((Point3::new(-1.0, 0.5, 0.5), Point3::new(4.0, 0.5, 0.5)));
+            assert_relative_eq!(seg.intersection(&mesh).unwrap().p, Point3::new(1.0, 0.5, 0.5), epsilon = COLLISION_EPSILON);
+
+            let hull = ConvexMesh::from(unit_cube().verts);
+            let inter = ray.intersection(&hull);
+            assert!(inter.is_none());
+            let ray = Ray{ p: Point3::new(0.5, 0.5, 5.0), d: Vector3::new(0.0, 0.0, -1.0) };
+            assert_relative_eq!(ray.intersection(&hull).unwrap().p, Point3::new(0.5, 0.5, 1.0), epsilon = 0.0001);
+            // Translated hulls are hit where they are moved to.
+            let mut hull = hull;
+            hull += Vector3::new(1.0, 0.0, 0.0);
+            assert!(ray.intersection(&hull).is_none());
+            let ray = Ray{ p: Point3::new(1.5, 0.5, 5.0), d: Vector3::new(0.0, 0.0, -1.0) };
+            assert_relative_eq!(ray.intersection(&hull).unwrap().p, Point3::new(1.5, 0.5, 1.0), epsilon = 0.0001);
+        }
+
+        #[test]
+        fn test_convex_mesh_bounds() {
+            let mut hull = ConvexMesh::from(unit_cube().verts);
+            hull += Vector3::new(10.0, 0.0, -2.0);
+            let bounds: AABB = hull.bounds();
+            assert_relative_eq!(bounds.c, Point3::new(10.5, 0.5, -1.5), epsilon = COLLISION_EPSILON);
+            assert_relative_eq!(bounds.r, Vector3::new(0.5, 0.5, 0.5), epsilon = COLLISION_EPSILON);
+        }
+
+        #[test]
+        fn test_convex_mesh_contacts() {
+            let terrain = unit_cube();
+            let mut rock = unit_cube().verts;
+            for v in rock.iter_mut() {
+                *v = Point3::new(0.3, 0.9, 0.3) + v.to_vec() * 0.4;
+            }
+            let rock = ConvexMesh::from(rock);
+            let mut contacts = Vec::new();
+            assert!(terrain.contacts(&rock, |c| contacts.push(c)));
+            assert!(!contacts.is_empty());
+            for c in contacts {
+                assert_eq!(c.t, 0.0);
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!(c.a.y, 1.0, epsilon = 0.001);
+                assert_relative_eq!(c.b.y, 0.9, epsilon = 0.001);
+            }
+            let floating = ConvexMesh::from(vec![
+                Point3::new(0.0, 2.0, 0.0), Point3::new(1.0, 2.0, 0.0),
+                Point3::new(0.0, 2.0, 1.0), Point3::new(0.0, 3.0, 0.0),
+            ]);
+            assert!(!terrain.contacts(&floating, |_| ()));
+        }
+
+        #[test]
+        fn test_internal_edges() {
+            let mut ground = Mesh::new();
+            for &(x, z) in [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)].iter() {
+                ground.push_vert(Point3::new(x, 0.0, z));
+            }
+            ground.push_face((0, 3, 2));
+            ground.push_face((0, 2, 1));
+            assert_eq!(ground.edges[0][2].neighbor, Some(1));
+            assert_eq!(ground.edges[1][0].neighbor, Some(0));
+            assert!(!ground.edges[0][2].convex);
+            // The ball rests on the second face but also touches the diagonal
+            // edge of the first, which must not push it sideways.
+            let ball = Sphere{ c: Point3::new(1.1, 0.45, 0.9), r: 0.5 };
+            let mut contacts = Vec::new();
+            assert!(ground.contacts(&ball, |c| contacts.push(c)));
+            assert_eq!(contacts.len(), 2);
+            for c in contacts {
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+            }
+
+            // The edges of a cube are convex, so the normal of a contact on
+            // one is kept.
+            let cube = unit_cube();
+            assert!(cube.edges.iter().all(|e| e.iter().all(|e| e.neighbor.is_some())));
+            let ball = Sphere{ c: Point3::new(1.2, 1.2, 0.5), r: 0.3 };
+            let mut contacts = Vec::new();
+            assert!(cube.contacts(&ball, |c| contacts.push(c)));
+            for c in contacts {
+                assert_relative_eq!(c.n, Vector3::new(1.0, 1.0, 0.0).normalize(), epsilon = 0.01);
+            }
+        }
+
+        #[test]
+        fn test_face_flags() {
+            let mut pane = Mesh::new();
+            for &(x, z) in [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)].iter() {
+                pane.push_vert(Point3::new(x, 0.0, z));
+            }
+            pane.push_face((0, 3, 2));
+            let below = Moving::sweep(Sphere{ c: Point3::new(0.5, -1.0, 1.0), r: 0.5 }, Vector3::new(0.0, 2.0, 0.0));
+            let above = Moving::sweep(Sphere{ c: Point3::new(0.5, 1.0, 1.0), r: 0.5 }, Vector3::new(0.0, -2.0, 0.0));
+            let capsule = Moving::sweep(
+                Capsule{ a: Point3::new(0.5, -1.0, 0.5), d: Vector3::new(0.0, 0.0, 1.0), r: 0.5 },
+                Vector3::new(0.0, 2.0, 0.0)
+            );
+            let up = Ray{ p: Point3::new(0.5, -1.0, 1.0), d: Vector3::new(0.0, 1.0, 0.0) };
+            let down = Ray{ p: Point3::new(0.5, 1.0, 1.0), d: Vector3::new(0.0, -1.0, 0.0) };
+
+            // One sided faces push objects behind them out the front.
+            let mut contacts = Vec::new();
+            assert!(pane.contacts(&below, |c| contacts.push(c)));
+            assert!(contacts.iter().all(|c| c.n.y > 0.0));
+            assert!(up.intersection(&pane).is_some());
+
+            pane.set_face_flags(0, FaceFlags{ double_sided: true, ..Default::default() });
+            let mut contacts = Vec::new();
+            assert!(pane.contacts(&below, |c| contacts.push(c)));
+            for c in contacts {
+                assert_relative_eq!(c.n, Vector3::new(0.0, -1.0, 0.0), epsilon = COLLISION_EPSILON);
+                assert_relative_eq!(c.t, 0.25, epsilon = COLLISION_EPSILON);
+            }
+            let mut contacts = Vec::new();
+            assert!(pane.contacts(&capsule, |c| contacts.push(c)));
+            assert!(contacts.iter().all(|c| c.n.y < 0.0));
+            // An object overlapping the back of the face is pushed out the
+            // back, with contact points on the back of the face.
+            let embedded = Sphere{ c: Point3::new(0.5, -0.2, 1.0), r: 0.5 };
+            let mut contacts = Vec::new();
+            assert!(pane.contacts(&embedded, |c| contacts.push(c)));
+            assert!(!contacts.is_empty());
+            for c in contacts {
+                assert_relative_eq!(c.n, Vector3::new(0.0, -1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!(c.a, Point3::new(0.5, 0.0, 1.0), epsilon = 0.001);
+                assert_relative_eq!(c.b, Point3::new(0.5, 0.3, 1.0), epsilon = 0.001);
+                assert_relative_eq!((c.a - c.b).dot(c.n), 0.3, epsilon = 0.001);
+            }
+            let mut contacts = Vec::new();
+            assert!(pane.contacts(&above, |c| contacts.push(c)));
+            assert!(contacts.iter().all(|c| c.n.y > 0.0));
+
+            pane.set_face_flags(0, FaceFlags{ one_way: true, ..Default::default() });
+            assert!(!pane.contacts(&below, |_| ()));
+            assert!(!pane.contacts(&capsule, |_| ()));
+            assert!(pane.contacts(&above, |_| ()));
+            assert!(up.intersection(&pane).is_none());
+            assert!(down.intersection(&pane).is_some());
+
+            pane.set_face_flags(0, FaceFlags{ no_collide: true, ..Default::default() });
+            assert!(!pane.contacts(&above, |_| ()));
+            assert!(down.intersection(&pane).is_none());
+        }
+
+        #[test]
+        fn test_moving_obb_contacts() {
+            let mesh = unit_cube();
+            let obb = OBB::new(Point3::new(0.5, 3.0, 0.5), Vector3::new(0.25, 0.25, 0.25), Quaternion::one());
+            let mut contacts = Vec::new();
+            assert!(mesh.contacts(&Moving::sweep(obb, Vector3::new(0.0, -4.0, 0.0)), |c| contacts.push(c)));
+            // The bottom faces are reached from behind and report nothing.
+            assert!(contacts.len() >= 4);
+            for c in contacts {
+                assert_relative_eq!(c.t, 0.4375, epsilon = 0.001);
+                assert_relative_eq!(c.a.y, 1.0, epsilon = 0.001);
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+            }
+
+            let tri = Triangle::from((Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 2.0), Point3::new(2.0, 0.0, 0.0)));
+            assert!(tri.normal().y > 0.0);
+            let below = OBB::new(Point3::new(0.5, -1.0, 0.5), Vector3::new(0.25, 0.25, 0.25), Quaternion::one());
+            assert!(!tri.contacts(&Moving::sweep(below, Vector3::new(0.0, 2.0, 0.0)), |_| ()));
+            // A box already overlapping the back is pushed out of the front.
+            let embedded = OBB::new(Point3::new(0.5, -0.1, 0.5), Vector3::new(0.25, 0.25, 0.25), Quaternion::one());
+            let mut contacts = Vec::new();
+            assert!(tri.contacts(&Moving::sweep(embedded, Vector3::new(0.0, 0.5, 0.0)), |c| contacts.push(c)));
+            for c in contacts {
+                assert_relative_eq!(c.t, 0.0);
+                assert_relative_eq!(c.n, Vector3::new(0.0, 1.0, 0.0), epsilon = 0.001);
+                assert_relative_eq!((c.a - c.b).dot(c.n), 0.35, epsilon = 0.001);
+            }
+        }
     }
 }
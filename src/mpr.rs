@@ -0,0 +1,224 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use cgmath::prelude::*;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::geom::*;
+use crate::gjk::PenetrationDepth;
+
+/// Maximum number of times the portal is refined before MPR settles for the
+/// best portal found so far.
+const MAX_ITERATIONS: usize = 64;
+
+/// Refinement stops once a new support point lies less than this distance
+/// beyond the portal.
+const MPR_TOLERANCE: f32 = 0.0001;
+
+/// Returns a point deep inside of the Minkowski difference, found by averaging
+/// its extremes along each axis.
+fn interior_point<S: Convex<SupportPoint>>(diff: &S) -> SupportPoint {
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    let mut p = Vector3::zero();
+    let mut a = Vector3::zero();
+    let mut b = Vector3::zero();
+    for &axis in axes.iter() {
+        for &d in [axis, -axis].iter() {
+            let s = diff.support(d);
+            p += s.p.to_vec();
+            a += s.a.to_vec();
+            b += s.b.to_vec();
+        }
+    }
+    SupportPoint {
+        p: Point3::from_vec(p / 6.0),
+        a: Point3::from_vec(a / 6.0),
+        b: Point3::from_vec(b / 6.0),
+    }
+}
+
+/// Interpolates the witness points of a triangle of support points at the
+/// given barycentric coordinates.
+fn witnesses(tri: [SupportPoint; 3], u: f32, v: f32, w: f32) -> (Point3<f32>, Point3<f32>) {
+    let a = tri[0].a.to_vec() * u + tri[1].a.to_vec() * v + tri[2].a.to_vec() * w;
+    let b = tri[0].b.to_vec() * u + tri[1].b.to_vec() * v + tri[2].b.to_vec() * w;
+    (Point3::from_vec(a), Point3::from_vec(b))
+}
+
+/// Finds the penetration of two overlapping shapes using Minkowski Portal
+/// Refinement, also known as XenoCollide. Returns None if the shapes do not
+/// overlap.
+///
+/// MPR converges faster than EPA for smooth shapes such as spheres and
+/// capsules, but the penetration it finds is along the ray from the interior
+/// of the Minkowski difference through the origin and is not guaranteed to
+/// be the minimum.
+pub fn mpr_penetration<A, B>(a: &A, b: &B) -> Option<PenetrationDepth>
+where
+    A: Convex,
+    B: Convex,
+{
+    mpr(&MinkowskiDiff { s1: a, s2: b })
+}
+
+fn mpr<S: Convex<SupportPoint>>(diff: &S) -> Option<PenetrationDepth> {
+    let mut v0 = interior_point(diff);
+    if v0.p.to_vec().magnitude2() <= COLLISION_EPSILON * COLLISION_EPSILON {
+        // The origin must not coincide with the interior point.
+        v0.p.x += 0.00001;
+    }
+
+    // Phase one: find a portal, a triangle of support points that the ray
+    // from v0 through the origin passes through.
+    let mut n = -v0.p.to_vec().normalize();
+    let mut v1 = diff.support(n);
+    if v1.p.to_vec().dot(n) <= 0.0 {
+        return None;
+    }
+    n = v0.p.to_vec().cross(v1.p.to_vec());
+    if n.magnitude2() <= COLLISION_EPSILON * COLLISION_EPSILON {
+        // The origin lies on the segment between v0 and v1.
+        let depth = v1.p.to_vec().magnitude();
+        let n = if depth > 0.0 { v1.p.to_vec() / depth } else { -n };
+        return Some(PenetrationDepth { a: v1.a, b: v1.b, n, depth });
+    }
+    let mut v2 = diff.support(n.normalize());
+    if v2.p.to_vec().dot(n) <= 0.0 {
+        return None;
+    }
+    n = (v1.p - v0.p).cross(v2.p - v0.p);
+    if n.dot(v0.p.to_vec()) > 0.0 {
+        std::mem::swap(&mut v1, &mut v2);
+        n = -n;
+    }
+    let mut v3;
+    let mut iterations = 0;
+    loop {
+        if iterations == MAX_ITERATIONS || n.magnitude2() <= f32::EPSILON {
+            return None;
+        }
+        iterations += 1;
+        v3 = diff.support(n.normalize());
+        if v3.p.to_vec().dot(n) <= 0.0 {
+            return None;
+        }
+        if v1.p.to_vec().cross(v3.p.to_vec()).dot(v0.p.to_vec()) < 0.0 {
+            v2 = v3;
+            n = (v1.p - v0.p).cross(v3.p - v0.p);
+            continue;
+        }
+        if v3.p.to_vec().cross(v2.p.to_vec()).dot(v0.p.to_vec()) < 0.0 {
+            v1 = v3;
+            n = (v3.p - v0.p).cross(v2.p - v0.p);
+            continue;
+        }
+        break;
+    }
+
+    // Phase two: move the portal towards the surface of the Minkowski
+    // difference until it can no longer be improved.
+    for i in 0..=MAX_ITERATIONS {
+        let mut n = (v2.p - v1.p).cross(v3.p - v1.p);
+        if n.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            return None;
+        }
+        n = n.normalize();
+        if n.dot(v1.p - v0.p) < 0.0 {
+            n = -n;
+        }
+        let v4: SupportPoint = diff.support(n);
+        if n.dot(v4.p.to_vec()) < 0.0 {
+            // The origin lies beyond the furthest extent of the difference.
+            return None;
+        }
+        let reach = n.dot(v4.p.to_vec()) - n.dot(v1.p.to_vec())
+            .max(n.dot(v2.p.to_vec()))
+            .max(n.dot(v3.p.to_vec()));
+        if reach <= MPR_TOLERANCE || i == MAX_ITERATIONS {
+            let depth = n.dot(v1.p.to_vec());
+            if depth < 0.0 {
+                return None;
+            }
+            let tri = Triangle::from((v1.p, v2.p, v3.p));
+            let (v, w, u) = tri.barycentric(Point3::from_vec(n * depth));
+            let (a, b) = witnesses([v1, v2, v3], u, v, w);
+            return Some(PenetrationDepth { a, b, n, depth });
+        }
+        // Replace the vertex of the portal on the far side of the plane
+        // through v0, v4 and the origin.
+        let v4v0 = v4.p.to_vec().cross(v0.p.to_vec());
+        if v1.p.to_vec().dot(v4v0) > 0.0 {
+            if v2.p.to_vec().dot(v4v0) > 0.0 {
+                v1 = v4;
+            } else {
+                v3 = v4;
+            }
+        } else if v3.p.to_vec().dot(v4v0) > 0.0 {
+            v2 = v4;
+        } else {
+            v1 = v4;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    mod mpr {
+        use cgmath::{EuclideanSpace, InnerSpace, One, Point3, Quaternion, Vector3};
+        use crate::collision::Contains;
+        use crate::geom::{Convex, OBB, Sphere};
+        use crate::gjk::{penetration_depth, penetration_depth_with, PenetrationSolver};
+        use crate::mpr::*;
+
+        #[test]
+        fn test_mpr_penetration() {
+            let s1 = Sphere{ c: Point3::new(0.0, 0.0, 0.0), r: 1.0 };
+            let s2 = Sphere{ c: Point3::new(1.5, 0.0, 0.0), r: 1.0 };
+            let pen = mpr_penetration(&s1, &s2).unwrap();
+            assert_relative_eq!(pen.depth, 0.5, epsilon = 0.001);
+            assert_relative_eq!(pen.n, Vector3::new(1.0, 0.0, 0.0), epsilon = 0.01);
+            assert_relative_eq!(pen.a, Point3::new(1.0, 0.0, 0.0), epsilon = 0.01);
+            assert_relative_eq!(pen.b, Point3::new(0.5, 0.0, 0.0), epsilon = 0.01);
+            let s3 = Sphere{ c: Point3::new(2.5, 0.0, 0.0), r: 1.0 };
+            assert!(mpr_penetration(&s1, &s3).is_none());
+            let pen = penetration_depth_with(&s1, &s2, PenetrationSolver::Mpr).unwrap();
+            assert_relative_eq!(pen.depth, 0.5, epsilon = 0.001);
+            assert_eq!(PenetrationSolver::default(), PenetrationSolver::Epa);
+
+            let obb = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 1.0, 2.0), Quaternion::one());
+            let sphere = Sphere{ c: Point3::new(0.0, 1.75, 0.0), r: 1.0 };
+            let pen = mpr_penetration(&obb, &sphere).unwrap();
+            let epa = penetration_depth(&obb, &sphere).unwrap();
+            assert_relative_eq!(pen.depth, epa.depth, epsilon = 0.001);
+            assert_relative_eq!(pen.n, epa.n, epsilon = 0.001);
+            assert!(mpr_penetration(&obb, &Sphere{ c: Point3::new(0.0, 2.5, 0.0), r: 1.0 }).is_none());
+        }
+
+        #[test]
+        fn test_mpr_witnesses() {
+            let floor = OBB::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 1.0, 2.0), Quaternion::one());
+            let block = OBB::new(Point3::new(0.2, 1.8, -0.15), Vector3::new(0.5, 1.0, 0.5), Quaternion::one());
+            let ball = Sphere{ c: Point3::new(0.3, 1.5, 0.2), r: 1.0 };
+            let pens = [mpr_penetration(&floor, &block).unwrap(), mpr_penetration(&floor, &ball).unwrap()];
+            for pen in pens.iter() {
+                assert_relative_eq!(pen.a - pen.b, pen.n * pen.depth, epsilon = 0.001);
+                // The witness on the floor is on its surface, furthest along n.
+                assert_relative_eq!(pen.n.dot(pen.a.to_vec()), pen.n.dot(floor.support(pen.n).to_vec()), epsilon = 0.001);
+                assert!(floor.contains(&(pen.a + -pen.n * 0.001)));
+            }
+        }
+    }
+}
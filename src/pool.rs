@@ -112,6 +112,12 @@ impl<T> Pool<T> {
         }
     }
 
+    /// Returns one past the largest index that has ever been occupied. Every
+    /// occupied index is less than this.
+    pub fn upper_bound(&self) -> usize {
+        self.entries.len()
+    }
+
     /// Returns the next available id for reuse if one exists.
     pub fn next_free(&self) -> Option<usize> {
         if let Some(free) = self.free_list {
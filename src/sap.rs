@@ -0,0 +1,319 @@
+// Copyright 2017 Matthew Plant. This file is part of MGF.
+//
+// MGF is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// MGF is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with MGF. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::vec::Vec;
+
+use cgmath::Vector3;
+use smallvec::SmallVec;
+
+use crate::bounds::Bound;
+use crate::broadphase::{Broadphase, BroadphaseParams, DefaultBroadphaseParams};
+use crate::collision::{Contains, Overlaps};
+use crate::geom::AABB;
+use crate::pool::Pool;
+
+/// An object stored in a SweepAndPrune.
+struct SapProxy<V> {
+    fat: AABB,
+    val: V,
+}
+
+/// The lower or upper end of the fat bounds of a proxy along an axis.
+#[derive(Copy, Clone, Debug)]
+struct Endpoint {
+    id: usize,
+    is_max: bool,
+}
+
+/// A sort based broadphase.
+///
+/// The ends of the fat AABBs of the proxies are kept sorted along each of the
+/// configured axes. Since objects move only a little between updates the
+/// lists are nearly sorted and are repaired with an insertion sort. Every
+/// swap of a lower end past an upper end, or the other way around, is where
+/// two proxies begin or stop overlapping along that axis, so the pairs are
+/// updated from the swaps alone. Fat bounds are computed exactly as they are
+/// for DynamicTree, so both produce the same set of pairs.
+pub struct SweepAndPrune<V, Params = DefaultBroadphaseParams>
+where
+    Params: BroadphaseParams
+{
+    proxies: Pool<SapProxy<V>>,
+    axes: SmallVec<[(usize, Vec<Endpoint>); 3]>,
+    moved: Vec<usize>,
+    /// Whether each proxy ID is in moved.
+    is_moved: Vec<bool>,
+    /// Pairs whose fat bounds overlap along every configured axis.
+    candidates: BTreeSet<(usize, usize)>,
+    pairs: BTreeSet<(usize, usize)>,
+    params: PhantomData<Params>,
+}
+
+impl<V, Params: BroadphaseParams> SweepAndPrune<V, Params> {
+    /// Creates a SweepAndPrune that sorts along all three axes.
+    pub fn new() -> Self {
+        Self::with_axes(&[0, 1, 2])
+    }
+
+    /// Creates a SweepAndPrune that sorts along the given axes, where 0, 1 and
+    /// 2 are x, y and z. For mostly flat scenes the vertical axis can be left
+    /// out.
+    pub fn with_axes(axes: &[usize]) -> Self {
+        if axes.is_empty() || axes.iter().any(|&axis| axis > 2) {
+            panic!("invalid sweep and prune axes {:?}", axes);
+        }
+        SweepAndPrune {
+            proxies: Pool::new(),
+            axes: axes.iter().map(|&axis| (axis, Vec::new())).collect(),
+            moved: Vec::new(),
+            is_moved: Vec::new(),
+            candidates: BTreeSet::new(),
+            pairs: BTreeSet::new(),
+            params: PhantomData,
+        }
+    }
+
+    /// Returns the fat bounds currently stored for a proxy.
+    pub fn fat_bounds(&self, id: usize) -> AABB {
+        self.proxies[id].fat
+    }
+
+    /// Buffers a proxy for the next update_pairs, unless it already is.
+    fn mark_moved(&mut self, id: usize) {
+        if !self.is_moved[id] {
+            self.is_moved[id] = true;
+            self.moved.push(id);
+        }
+    }
+}
+
+impl<V, Params: BroadphaseParams> Broadphase<V> for SweepAndPrune<V, Params> {
+    fn create_proxy(&mut self, bounds: AABB, val: V) -> usize {
+        let id = self.proxies.push(SapProxy{ fat: bounds + Params::MARGIN, val });
+        for (_, endpoints) in self.axes.iter_mut() {
+            endpoints.push(Endpoint{ id, is_max: false });
+            endpoints.push(Endpoint{ id, is_max: true });
+        }
+        if self.is_moved.len() <= id {
+            self.is_moved.resize(id + 1, false);
+        }
+        self.mark_moved(id);
+        id
+    }
+
+    fn destroy_proxy(&mut self, id: usize) -> V {
+        let proxy = self.proxies.remove(id);
+        for (_, endpoints) in self.axes.iter_mut() {
+            endpoints.retain(|e| e.id != id);
+        }
+        if self.is_moved[id] {
+            self.is_moved[id] = false;
+            self.moved.retain(|&moved| moved != id);
+        }
+        self.candidates.retain(|&(a, b)| a != id && b != id);
+        self.pairs.retain(|&(a, b)| a != id && b != id);
+        proxy.val
+    }
+
+    /// Updates the bounds of a proxy that has moved by displacement since its
+    /// last update. If the new bounds are still enclosed by the proxy's fat
+    /// bounds nothing is changed and false is returned.
+    fn move_proxy(&mut self, id: usize, bounds: AABB, displacement: Vector3<f32>) -> bool {
+        if self.proxies[id].fat.contains(&bounds) {
+            return false;
+        }
+        let fat = bounds + Params::MARGIN;
+        self.proxies[id].fat =
+            AABB::combine(&fat, &(fat + displacement * Params::DISPLACEMENT_MULTIPLIER));
+        self.mark_moved(id);
+        true
+    }
+
+    /// Re-sorts each axis, updating the pairs from the swaps made, and then
+    /// checks the pairs of proxies that moved against their whole fat bounds.
+    /// Nothing is done if no proxy was created or moved since the last update.
+    fn update_pairs<F>(&mut self, mut callback: F)
+    where
+        F: FnMut(usize, usize)
+    {
+        if self.moved.is_empty() {
+            return;
+        }
+        let proxies = &self.proxies;
+        let sorted_axes: SmallVec<[usize; 3]> = self.axes.iter().map(|&(axis, _)| axis).collect();
+        // Lower ends are ordered before upper ends at the same position, since
+        // touching bounds overlap.
+        let key = |e: Endpoint, axis: usize| {
+            let fat = &proxies[e.id].fat;
+            if e.is_max {
+                (fat.c[axis] + fat.r[axis], true)
+            } else {
+                (fat.c[axis] - fat.r[axis], false)
+            }
+        };
+        let overlaps_sorted = |a: usize, b: usize| {
+            let (a, b) = (&proxies[a].fat, &proxies[b].fat);
+            sorted_axes.iter().all(|&axis| (a.c[axis] - b.c[axis]).abs() <= a.r[axis] + b.r[axis])
+        };
+        let candidates = &mut self.candidates;
+        let pairs = &mut self.pairs;
+        for (axis, endpoints) in self.axes.iter_mut() {
+            let axis = *axis;
+            for i in 1..endpoints.len() {
+                let e = endpoints[i];
+                let e_key = key(e, axis);
+                let mut j = i;
+                while j > 0 && key(endpoints[j - 1], axis) > e_key {
+                    let f = endpoints[j - 1];
+                    let pair = if e.id < f.id { (e.id, f.id) } else { (f.id, e.id) };
+                    if !e.is_max && f.is_max {
+                        // The proxies now overlap along this axis, and possibly
+                        // along the others.
+                        if overlaps_sorted(e.id, f.id) {
+                            candidates.insert(pair);
+                        }
+                    } else if e.is_max && !f.is_max {
+                        candidates.remove(&pair);
+                        pairs.remove(&pair);
+                    }
+                    endpoints[j] = f;
+                    j -= 1;
+                }
+                endpoints[j] = e;
+            }
+        }
+
+        // Axes that are not sorted are only checked for proxies that moved.
+        for &(a, b) in self.candidates.iter() {
+            if !self.is_moved[a] && !self.is_moved[b] {
+                continue;
+            }
+            if self.proxies[a].fat.overlaps(&self.proxies[b].fat) {
+                if self.pairs.insert((a, b)) {
+                    callback(a, b);
+                }
+            } else {
+                self.pairs.remove(&(a, b));
+            }
+        }
+        for &id in self.moved.iter() {
+            self.is_moved[id] = false;
+        }
+        self.moved.clear();
+    }
+
+    fn pairs<F>(&self, mut callback: F)
+    where
+        F: FnMut(usize, usize)
+    {
+        for &(a, b) in self.pairs.iter() {
+            callback(a, b);
+        }
+    }
+}
+
+impl<V, Params: BroadphaseParams> Default for SweepAndPrune<V, Params> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V, Params: BroadphaseParams> Index<usize> for SweepAndPrune<V, Params> {
+    type Output = V;
+
+    fn index(&self, id: usize) -> &V {
+        &self.proxies[id].val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod sap {
+        use cgmath::{Point3, Vector3};
+        use crate::broadphase::*;
+        use crate::geom::AABB;
+        use crate::sap::*;
+
+        fn unit_box(x: f32, z: f32) -> AABB {
+            AABB{ c: Point3::new(x, 0.0, z), r: Vector3::new(0.5, 0.5, 0.5) }
+        }
+
+        fn collect_pairs<B: Broadphase<usize>>(broadphase: &B) -> Vec<(usize, usize)> {
+            let mut pairs = Vec::new();
+            broadphase.pairs(|a, b| pairs.push((broadphase[a], broadphase[b])));
+            pairs.sort();
+            pairs
+        }
+
+        #[test]
+        fn test_sweep_and_prune() {
+            let mut sap: SweepAndPrune<usize> = SweepAndPrune::with_axes(&[0, 2]);
+            let mut tree: DynamicTree<usize> = DynamicTree::new();
+            let boxes: Vec<AABB> = (0..16)
+                .map(|i| unit_box((i % 4) as f32 * 1.1, (i / 4) as f32 * 3.0))
+                .collect();
+            let mut sap_ids = Vec::new();
+            let mut tree_ids = Vec::new();
+            for (i, &bounds) in boxes.iter().enumerate() {
+                sap_ids.push(sap.create_proxy(bounds, i));
+                tree_ids.push(tree.create_proxy(bounds, i));
+            }
+            let mut new_pairs = 0;
+            sap.update_pairs(|_, _| new_pairs += 1);
+            tree.update_pairs(|_, _| {});
+            assert_eq!(new_pairs, 12);
+            assert_eq!(collect_pairs(&sap), collect_pairs(&tree));
+
+            // Move a box from the first row into the second.
+            let moved = unit_box(0.5, 3.0);
+            let displacement = Vector3::new(0.5, 0.0, 3.0);
+            assert!(sap.move_proxy(sap_ids[0], moved, displacement));
+            assert!(tree.move_proxy(tree_ids[0], moved, displacement));
+            let mut new_pairs = Vec::new();
+            sap.update_pairs(|a, b| new_pairs.push((a, b)));
+            tree.update_pairs(|_, _| {});
+            assert!(new_pairs.contains(&(sap_ids[0], sap_ids[4])));
+            assert_eq!(collect_pairs(&sap), collect_pairs(&tree));
+
+            // Lift the box along the axis that is not sorted, then put it back.
+            for &(y, new) in [(5.0, 0), (0.0, 2)].iter() {
+                let bounds = AABB{ c: Point3::new(0.5, y, 3.0), ..moved };
+                let displacement = Vector3::new(0.0, 0.0, 0.0);
+                sap.move_proxy(sap_ids[0], bounds, displacement);
+                tree.move_proxy(tree_ids[0], bounds, displacement);
+                let mut new_pairs = 0;
+                sap.update_pairs(|_, _| new_pairs += 1);
+                tree.update_pairs(|_, _| {});
+                assert_eq!(new_pairs, new);
+                assert_eq!(collect_pairs(&sap), collect_pairs(&tree));
+            }
+
+            // Separate two boxes along a sorted axis.
+            let far = unit_box(20.0, 0.0);
+            sap.move_proxy(sap_ids[3], far, Vector3::new(0.0, 0.0, 0.0));
+            tree.move_proxy(tree_ids[3], far, Vector3::new(0.0, 0.0, 0.0));
+            sap.update_pairs(|_, _| panic!("no new pairs"));
+            tree.update_pairs(|_, _| {});
+            assert_eq!(collect_pairs(&sap), collect_pairs(&tree));
+
+            sap.destroy_proxy(sap_ids[4]);
+            tree.destroy_proxy(tree_ids[4]);
+            assert_eq!(collect_pairs(&sap), collect_pairs(&tree));
+        }
+    }
+}
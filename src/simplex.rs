@@ -18,14 +18,12 @@
 
 use std::f32;
 use std::fmt;
-use std::mem;
-use std::collections::HashMap;
 
 use cgmath::prelude::*;
 use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
 use crate::geom::*;
 use crate::collision::*;
-use crate::pool::*;
+use crate::gjk::expand_polytope;
 
 pub struct Simplex<Point = Point3<f32>>
 where
@@ -414,142 +412,77 @@ where
     fn len(&self) -> usize { 4 }
 }
 
-#[derive(Default)]
-struct EdgeMap {
-    map: HashMap<[ (u32, u32, u32); 2 ], [ (Point3<f32>, Point3<f32>); 2 ]>,
-}
-
-impl EdgeMap {
-    fn add_edge(&mut self, a: SupportPoint, b: SupportPoint) {
-        let la = (a.a, a.b);
-        let lb = (b.a, b.b);
-        let a = unsafe {
-            (
-                mem::transmute::<f32, u32>(a.p.x),
-                mem::transmute::<f32, u32>(a.p.y),
-                mem::transmute::<f32, u32>(a.p.z),
-            )
-        };
-        let b = unsafe {
-            (
-                mem::transmute::<f32, u32>(b.p.x),
-                mem::transmute::<f32, u32>(b.p.y),
-                mem::transmute::<f32, u32>(b.p.z),
-            )
-        };
-        let ba = [ b, a ];
-        if self.map.contains_key(&ba) {
-            self.map.remove(&ba);
-            return;
-        }
-        self.map.insert(
-            [ a, b ],
-            [ la, lb ]
-        );
-    }
-}
-        
-
 impl Simplex<SupportPoint> {
-    /// Generates a contact from a simplex. This uses the EPA algorithm. Based on
-    /// the description here: http://hacktank.net/blog/?p=119
-    pub fn compute_contact<S1, S2>(&self, s1: &S1, s2: &S2) -> Contact
+    /// Generates a contact from a simplex enclosing the origin using the
+    /// Expanding Polytope Algorithm. Simplices with fewer than four vertices
+    /// are grown into a tetrahedron first, and shapes that are only touching
+    /// produce a contact with no depth. Returns None if no direction of
+    /// penetration can be found, such as for two coincident points.
+    pub fn compute_contact<S1, S2>(&self, s1: &S1, s2: &S2) -> Option<Contact>
     where
         S1: Convex,
         S2: Convex
     {
-        if self.state.len() != 4 {
-            panic!("simplex is too small");
-        }
         let diff = MinkowskiDiff{ s1, s2 };
-        let [ a, b, c, d ] = self.points;
-        let mut tris = Pool::from(
+        let pen = expand_polytope(&diff, &self.points[..self.state.len()])?;
+        if pen.n.is_zero() {
+            return None;
+        }
+        Some(Contact {
+            a: pen.a,
+            b: pen.b,
+            n: pen.n,
+            t: 0.0
+        })
+    }
+}
+    
+
+#[cfg(test)]
+mod tests {
+    mod simplex {
+        use cgmath::{Point3, Vector3};
+        use crate::collision::Contact;
+        use crate::geom::*;
+        use crate::simplex::*;
+
+        /// Computes the contact between a unit box at the origin and one
+        /// above it at height y, starting from simplices of each size.
+        fn box_contacts(y: f32) -> Vec<Option<Contact>> {
+            let a = AABB{ c: Point3::new(0.0, 0.0, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let b = AABB{ c: Point3::new(0.0, y, 0.0), r: Vector3::new(1.0, 1.0, 1.0) };
+            let diff = MinkowskiDiff{ s1: &a, s2: &b };
+            let s = |x: f32, y: f32, z: f32| -> SupportPoint { diff.support(Vector3::new(x, y, z)) };
             vec![
-                (a, b, c),
-                (a, c, d),
-                (a, d, b),
-                (b, d, c)
+                Simplex::from(s(0.0, 1.0, 0.0)).compute_contact(&a, &b),
+                Simplex::from([s(0.0, 1.0, 0.0), s(0.0, -1.0, 0.0)]).compute_contact(&a, &b),
+                Simplex::from([s(1.0, 1.0, 0.0), s(-1.0, 1.0, 0.0), s(0.0, -1.0, 1.0)]).compute_contact(&a, &b),
+                Simplex::from([
+                    s(1.0, 1.0, 1.0), s(-1.0, 1.0, -1.0), s(1.0, -1.0, -1.0), s(-1.0, -1.0, 1.0)
+                ]).compute_contact(&a, &b),
             ]
-        );
-        let mut edges = EdgeMap::default();
-        const MAX_ITERATIONS: usize = 100;
-        for iter in 0..=MAX_ITERATIONS {
-            let (closest_dist, closest_i, closest_n) = {
-                let mut closest_dist = f32::INFINITY;
-                let mut closest_i = 0usize;
-                let mut closest_n = Vector3::zero();
-                for (i, (a, b, c)) in tris.iter() {
-                    let tri = Triangle::from((a.p, b.p, c.p));
-                    let n = tri.normal();
-                    let dist = n.dot(a.p.to_vec()).abs();
-                    if closest_dist > dist {
-                        closest_dist = dist;
-                        closest_i = i;
-                        closest_n = n;
-                    }
-                }
-                (closest_dist, closest_i, closest_n)
-            };
-            let closest_tri = {
-                let (a, b, c) = tris[closest_i];
-                ( Triangle::from((a.p, b.p, c.p)), Triangle::from((a.a, b.a, c.a)) )
-            };
-            let support: SupportPoint = diff.support(closest_n);
-            let v = closest_n.dot(support.p.to_vec()) - closest_dist;
-            if v < COLLISION_EPSILON || iter == MAX_ITERATIONS {
-                let (u, v, w ) = closest_tri.0.barycentric(Point3::from_vec(closest_dist * closest_n));
-                let a = u * closest_tri.1.a + v * closest_tri.1.b + w * closest_tri.1.c;
-                return Contact {
-                    a: Point3::from_vec(a),
-                    b: Point3::from_vec(a - closest_dist*closest_n),
-                    n: closest_n,
-                    t: 0.0
-                };
-            }
-            // TODO: fix this being a thing.
-            let mut to_remove = Vec::new();
-            for (i, (a, b, c)) in tris.iter() {
-                let n = Triangle::from((a.p, b.p, c.p)).normal();
-                if n.dot(support.p - a.p) > 0.0 {
-                    edges.add_edge(*a, *b);
-                    edges.add_edge(*b, *c);
-                    edges.add_edge(*c, *a);
-                    to_remove.push(i);
-                }
-            }
-            for i in to_remove {
-                tris.remove(i);
+        }
+
+        #[test]
+        fn test_compute_contact() {
+            // Simplices with fewer than four vertices are grown before EPA.
+            for contact in box_contacts(1.5) {
+                let contact = contact.unwrap();
+                assert_relative_eq!(contact.n, Vector3::new(0.0, 1.0, 0.0), epsilon = COLLISION_EPSILON);
+                assert_relative_eq!((contact.a - contact.b).dot(contact.n), 0.5, epsilon = COLLISION_EPSILON);
+                assert_relative_eq!(contact.a.y, 1.0, epsilon = COLLISION_EPSILON);
             }
-            for ([( apx, apy, apz ), ( bpx, bpy, bpz ) ], [ la, lb ]) in edges.map.iter() {
-                let a = unsafe {
-                    (
-                        mem::transmute::<u32, f32>(*apx),
-                        mem::transmute::<u32, f32>(*apy),
-                        mem::transmute::<u32, f32>(*apz)
-                    )
-                };
-                let b = unsafe {
-                    (
-                        mem::transmute::<u32, f32>(*bpx),
-                        mem::transmute::<u32, f32>(*bpy),
-                        mem::transmute::<u32, f32>(*bpz)
-                    )
-                };
-                let a = SupportPoint {
-                    p: Point3::new(a.0, a.1, a.2),
-                    a: la.0,
-                    b: la.1
-                };
-                let b = SupportPoint {
-                    p: Point3::new(b.0, b.1, b.2),
-                    a: lb.0,
-                    b: lb.1
-                };
-                tris.push((support, a, b));
+            // Touching boxes still have a direction to be separated in.
+            for contact in box_contacts(2.0) {
+                let contact = contact.unwrap();
+                assert_relative_eq!(contact.n, Vector3::new(0.0, 1.0, 0.0), epsilon = COLLISION_EPSILON);
+                assert_relative_eq!(contact.a, contact.b, epsilon = COLLISION_EPSILON);
             }
-            edges.map.clear();
+            // Two coincident points give no direction at all.
+            let p = Point3::new(1.0, 2.0, 3.0);
+            let diff = MinkowskiDiff{ s1: &p, s2: &p };
+            let vertex: SupportPoint = diff.support(Vector3::new(1.0, 0.0, 0.0));
+            assert!(Simplex::from(vertex).compute_contact(&p, &p).is_none());
         }
-        unreachable!()
     }
 }
-    